@@ -1,10 +1,16 @@
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
 use thiserror::Error;
 
-use crate::config::rates::BASE_CURRENCY;
+use crate::config::{
+    rates::{CRYPTOCOMPARE_TOKENS_LIMIT, RATES_REQUEST_TIMEOUT_SECS},
+    settings::{RateSource, RatesSettings},
+};
 
 #[derive(Error, Debug)]
 pub enum RatesApiError {
@@ -24,35 +30,280 @@ pub enum RatesApiError {
     ParseResponseError(String, String),
 }
 
-pub async fn get_cryptocompare_prices(
-    fsyms: &[&str],
-) -> Result<HashMap<String, f64>, RatesApiError> {
-    let fsyms_str = fsyms.join(",");
-    let url = format!(
-        "https://min-api.cryptocompare.com/data/pricemulti?fsyms={}&tsyms={}",
-        fsyms_str, BASE_CURRENCY
-    );
-    let client = Client::new();
-    let response = client.get(&url).send().await?;
-    let body: Value = response.json().await?;
-
-    parse_crypto_response(body)
+/// Prices per lowercase currency code.
+pub type RatesFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<HashMap<String, f64>, RatesApiError>> + Send + 'a>>;
+
+/// What the prices of a provider are quoted as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// Price of one ZIL in each currency.
+    Zil,
+    /// Price of one unit of each currency in the base currency.
+    Cross,
+}
+
+/// An upstream of rates.
+pub trait RatesProvider: Send + Sync {
+    fn source(&self) -> RateSource;
+
+    fn quoting(&self) -> Quoting {
+        Quoting::Zil
+    }
+
+    /// Prices of those of `currencies` the upstream quotes.
+    fn fetch<'a>(&'a self, currencies: &'a [String]) -> RatesFuture<'a>;
+}
+
+/// A configured provider with its weight in the median.
+pub struct WeightedProvider {
+    pub provider: Box<dyn RatesProvider>,
+    pub weight: f64,
+}
+
+pub struct CoinGecko {
+    client: Client,
+    url: String,
+}
+
+pub struct CryptoCompare {
+    client: Client,
+    base_url: String,
+    base_currency: String,
+}
+
+pub struct Metals {
+    client: Client,
+    base_url: String,
+    base_currency: String,
+    api_key: Option<String>,
+}
+
+/// The configured sources, sharing one HTTP client. The metals.dev key is
+/// read from `METALS_API_KEY`.
+pub fn providers(settings: &RatesSettings) -> Vec<WeightedProvider> {
+    // A hung upstream would otherwise stall the whole rates job.
+    let client = Client::builder()
+        .timeout(Duration::from_secs(RATES_REQUEST_TIMEOUT_SECS))
+        .build()
+        .expect("Cannot build rates HTTP client.");
+
+    settings
+        .sources
+        .iter()
+        .map(|s| {
+            let provider: Box<dyn RatesProvider> = match s.source {
+                RateSource::Coingecko => {
+                    Box::new(CoinGecko::new(client.clone(), &settings.coingecko_url))
+                }
+                RateSource::Cryptocompare => Box::new(CryptoCompare::new(
+                    client.clone(),
+                    &settings.cryptocompare_url,
+                    &settings.base_currency,
+                )),
+                RateSource::Metals => Box::new(Metals::new(
+                    client.clone(),
+                    &settings.metals_url,
+                    &settings.base_currency,
+                    env::var("METALS_API_KEY").ok(),
+                )),
+            };
+
+            WeightedProvider {
+                provider,
+                weight: s.weight,
+            }
+        })
+        .collect()
+}
+
+impl CoinGecko {
+    /// `url` is the full `simple/price` endpoint.
+    pub fn new(client: Client, url: &str) -> Self {
+        CoinGecko {
+            client,
+            url: url.to_string(),
+        }
+    }
+}
+
+impl RatesProvider for CoinGecko {
+    fn source(&self) -> RateSource {
+        RateSource::Coingecko
+    }
+
+    fn fetch<'a>(&'a self, currencies: &'a [String]) -> RatesFuture<'a> {
+        Box::pin(async move {
+            let request = self.client.get(&self.url).query(&[
+                ("ids", "zilliqa".to_string()),
+                ("vs_currencies", currencies.join(",").to_lowercase()),
+            ]);
+
+            parse_coingecko_response(get_json(request).await?)
+        })
+    }
+}
+
+impl CryptoCompare {
+    pub fn new(client: Client, base_url: &str, base_currency: &str) -> Self {
+        CryptoCompare {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            base_currency: base_currency.to_uppercase(),
+        }
+    }
+
+    /// Price of each of `fsyms` in the base currency.
+    pub async fn prices(&self, fsyms: &[&str]) -> Result<HashMap<String, f64>, RatesApiError> {
+        let request = self
+            .client
+            .get(format!("{}/data/pricemulti", self.base_url))
+            .query(&[
+                ("fsyms", fsyms.join(",")),
+                ("tsyms", self.base_currency.clone()),
+            ]);
+
+        parse_crypto_response(get_json(request).await?, &self.base_currency)
+    }
+}
+
+impl RatesProvider for CryptoCompare {
+    fn source(&self) -> RateSource {
+        RateSource::Cryptocompare
+    }
+
+    /// ZIL price from the prices of ZIL and each currency in the base
+    /// currency.
+    fn fetch<'a>(&'a self, currencies: &'a [String]) -> RatesFuture<'a> {
+        Box::pin(async move {
+            let base = &self.base_currency;
+            let mut symbols: Vec<String> = currencies.iter().map(|c| c.to_uppercase()).collect();
+            let mut prices = HashMap::new();
+
+            symbols.push("ZIL".to_string());
+
+            for chunk in symbols.chunks(CRYPTOCOMPARE_TOKENS_LIMIT) {
+                let chunk: Vec<&str> = chunk.iter().map(String::as_str).collect();
+
+                prices.extend(self.prices(&chunk).await?);
+            }
+
+            let zil = prices
+                .get("ZIL")
+                .copied()
+                .ok_or_else(|| RatesApiError::ApiError("missing ZIL price".to_string()))?;
+
+            Ok(currencies
+                .iter()
+                .filter_map(|currency| {
+                    let code = currency.to_uppercase();
+                    let price = if code == *base {
+                        1.0
+                    } else {
+                        *prices.get(&code)?
+                    };
+
+                    (price > 0.0).then(|| (code.to_lowercase(), zil / price))
+                })
+                .collect())
+        })
+    }
+}
+
+impl Metals {
+    pub fn new(
+        client: Client,
+        base_url: &str,
+        base_currency: &str,
+        api_key: Option<String>,
+    ) -> Self {
+        Metals {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            base_currency: base_currency.to_uppercase(),
+            api_key,
+        }
+    }
+}
+
+impl RatesProvider for Metals {
+    fn source(&self) -> RateSource {
+        RateSource::Metals
+    }
+
+    fn quoting(&self) -> Quoting {
+        Quoting::Cross
+    }
+
+    /// Cross-rates in the base currency, without the base currency itself.
+    fn fetch<'a>(&'a self, currencies: &'a [String]) -> RatesFuture<'a> {
+        Box::pin(async move {
+            let api_key = self
+                .api_key
+                .as_deref()
+                .ok_or_else(|| RatesApiError::EnvVar("METALS_API_KEY not set".to_string()))?;
+            let request = self
+                .client
+                .get(format!("{}/v1/latest", self.base_url))
+                .query(&[
+                    ("api_key", api_key),
+                    ("currency", &self.base_currency),
+                    ("unit", "g"),
+                ]);
+            let mut prices = parse_metals_response(get_json(request).await?)?;
+
+            Ok(currencies
+                .iter()
+                .map(|c| c.to_uppercase())
+                .filter(|code| *code != self.base_currency)
+                .filter_map(|code| {
+                    let price = prices.remove(&code)?;
+
+                    Some((code.to_lowercase(), price))
+                })
+                .collect())
+        })
+    }
 }
 
-pub async fn get_metals_prices() -> Result<HashMap<String, f64>, RatesApiError> {
-    let api_key = env::var("METALS_API_KEY")
-        .map_err(|_| RatesApiError::EnvVar("METALS_API_KEY not set".to_string()))?;
-    let url = format!(
-        "https://api.metals.dev/v1/latest?api_key={}&currency={}&unit=g",
-        api_key, BASE_CURRENCY,
-    );
-    let client = Client::new();
-    let response = client.get(&url).send().await?;
-    let body: Value = response.json().await?;
-    parse_metals_response(body)
+/// The JSON body of a response. An error status with a body that is not
+/// JSON is an `ApiError`; error bodies in JSON are left to the parsers.
+/// Transport errors drop the URL, whose query may hold an API key.
+async fn get_json(request: RequestBuilder) -> Result<Value, RatesApiError> {
+    let response = request.send().await.map_err(|e| e.without_url())?;
+    let status = response.status();
+    let text = response.text().await.map_err(|e| e.without_url())?;
+
+    serde_json::from_str(&text).map_err(|e| {
+        if status.is_success() {
+            RatesApiError::ParseResponseError(e.to_string(), text)
+        } else {
+            RatesApiError::ApiError(format!("HTTP {}", status))
+        }
+    })
+}
+
+fn parse_coingecko_response(body: Value) -> Result<HashMap<String, f64>, RatesApiError> {
+    let Some(prices) = body.get("zilliqa").and_then(|z| z.as_object()) else {
+        let message = body
+            .get("status")
+            .and_then(|s| s.get("error_message"))
+            .and_then(|m| m.as_str())
+            .unwrap_or("missing zilliqa prices");
+
+        return Err(RatesApiError::ApiError(message.to_string()));
+    };
+
+    Ok(prices
+        .iter()
+        .filter_map(|(currency, price)| Some((currency.to_lowercase(), price.as_f64()?)))
+        .collect())
 }
 
-fn parse_crypto_response(body: Value) -> Result<HashMap<String, f64>, RatesApiError> {
+fn parse_crypto_response(
+    body: Value,
+    base_currency: &str,
+) -> Result<HashMap<String, f64>, RatesApiError> {
     if body.get("Response") == Some(&Value::String("Error".to_string())) {
         let message = body
             .get("Message")
@@ -63,7 +314,7 @@ fn parse_crypto_response(body: Value) -> Result<HashMap<String, f64>, RatesApiEr
     let mut result = HashMap::new();
     if let Some(obj) = body.as_object() {
         for (key, value) in obj {
-            if let Some(price) = value.get(BASE_CURRENCY).and_then(|v| v.as_f64()) {
+            if let Some(price) = value.get(base_currency).and_then(|v| v.as_f64()) {
                 result.insert(key.clone(), price);
             }
         }
@@ -126,7 +377,9 @@ fn parse_metals_response(body: Value) -> Result<HashMap<String, f64>, RatesApiEr
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
+    use crate::config::rates::BASE_CURRENCY;
+    use mockito::{Matcher, Server};
+    use serde_json::{json, Map};
 
     #[tokio::test]
     async fn test_parse_metals_response_success() {
@@ -258,21 +511,230 @@ mod tests {
         mock_map.insert("EKO".to_string(), eko);
         let mock = Value::Object(mock_map);
 
-        let res = parse_crypto_response(mock).unwrap();
+        let res = parse_crypto_response(mock, BASE_CURRENCY).unwrap();
         assert_eq!(res.get("ETH"), Some(&0.02406));
         assert_eq!(res.get("ZIL"), Some(&1.3e-7));
         assert_eq!(res.get("EKO"), Some(&1.3e-7));
     }
 
+    #[test]
+    fn test_parse_coingecko_response() {
+        let res = parse_coingecko_response(json!({ "zilliqa": { "usd": 0.02, "eur": 0.018 } }));
+        assert_eq!(res.unwrap().get("eur"), Some(&0.018));
+
+        let res = parse_coingecko_response(json!({
+            "status": { "error_code": 429, "error_message": "Rate limit exceeded" }
+        }));
+        assert!(matches!(res, Err(RatesApiError::ApiError(m)) if m == "Rate limit exceeded"));
+    }
+
+    fn currencies(codes: &[&str]) -> Vec<String> {
+        codes.iter().map(ToString::to_string).collect()
+    }
+
+    async fn serve(server: &mut Server, path: &str, status: usize, body: &str) -> mockito::Mock {
+        server
+            .mock("GET", path)
+            .match_query(Matcher::Any)
+            .with_status(status)
+            .with_header("content-type", "application/json")
+            .with_body(body)
+            .create_async()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_coingecko_provider() {
+        let mut server = Server::new_async().await;
+        let url = format!("{}/api/v3/simple/price", server.url());
+        let provider = CoinGecko::new(Client::new(), &url);
+        let mock = server
+            .mock("GET", "/api/v3/simple/price")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("ids".into(), "zilliqa".into()),
+                Matcher::UrlEncoded("vs_currencies".into(), "usd,eur".into()),
+            ]))
+            .with_body(r#"{"zilliqa":{"usd":0.02,"eur":0.018}}"#)
+            .create_async()
+            .await;
+        let res = provider.fetch(&currencies(&["USD", "EUR"])).await.unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(res.get("usd"), Some(&0.02));
+        assert_eq!(res.get("eur"), Some(&0.018));
+
+        let path = "/api/v3/simple/price";
+        server.reset();
+        let _mock = serve(
+            &mut server,
+            path,
+            429,
+            r#"{"status":{"error_code":429,"error_message":"Rate limit exceeded"}}"#,
+        )
+        .await;
+        let res = provider.fetch(&currencies(&["USD"])).await;
+        assert!(matches!(res, Err(RatesApiError::ApiError(m)) if m == "Rate limit exceeded"));
+
+        server.reset();
+        let _mock = serve(&mut server, path, 200, "{\"zilliqa\":").await;
+        let res = provider.fetch(&currencies(&["USD"])).await;
+        assert!(
+            matches!(res, Err(RatesApiError::ParseResponseError(_, body)) if body == "{\"zilliqa\":")
+        );
+
+        server.reset();
+        let _mock = serve(&mut server, path, 503, "<html>unavailable</html>").await;
+        let res = provider.fetch(&currencies(&["USD"])).await;
+        assert!(matches!(res, Err(RatesApiError::ApiError(m)) if m.contains("503")));
+    }
+
     #[tokio::test]
     async fn test_cryptocompare() {
+        let mut server = Server::new_async().await;
+        let provider = CryptoCompare::new(Client::new(), &server.url(), BASE_CURRENCY);
         let tokens = ["BNB", "ETH", "USDT", "USDC", "JPY", "RUB", "EKO"];
-        let result = get_cryptocompare_prices(&tokens).await.unwrap();
+        let body: Map<String, Value> = tokens
+            .iter()
+            .map(|t| (t.to_string(), json!({ BASE_CURRENCY: 1.5 })))
+            .collect();
+        let mock = server
+            .mock("GET", "/data/pricemulti")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("fsyms".into(), tokens.join(",")),
+                Matcher::UrlEncoded("tsyms".into(), BASE_CURRENCY.into()),
+            ]))
+            .with_body(Value::Object(body).to_string())
+            .create_async()
+            .await;
+        let result = provider.prices(&tokens).await.unwrap();
+
+        mock.assert_async().await;
         for token in tokens {
             assert!(result.contains_key(token), "Expected key {}", token);
         }
     }
 
+    #[tokio::test]
+    async fn test_cryptocompare_provider() {
+        let mut server = Server::new_async().await;
+        let provider = CryptoCompare::new(Client::new(), &server.url(), "aed");
+        let path = "/data/pricemulti";
+        let mock = serve(
+            &mut server,
+            path,
+            200,
+            r#"{"USD":{"AED":3.5},"ZIL":{"AED":0.07}}"#,
+        )
+        .await;
+        let res = provider
+            .fetch(&currencies(&["USD", "AED", "EUR"]))
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert!((res["usd"] - 0.02).abs() < 1e-12);
+        assert_eq!(res.get("aed"), Some(&0.07));
+        assert!(!res.contains_key("eur"));
+
+        server.reset();
+        let _mock = serve(
+            &mut server,
+            path,
+            200,
+            r#"{"Response":"Error","Message":"fsyms param is invalid."}"#,
+        )
+        .await;
+        let res = provider.fetch(&currencies(&["USD"])).await;
+        assert!(matches!(res, Err(RatesApiError::ApiError(m)) if m == "fsyms param is invalid."));
+
+        server.reset();
+        let _mock = serve(&mut server, path, 200, r#"{"USD":{"AED":3.5}}"#).await;
+        let res = provider.fetch(&currencies(&["USD"])).await;
+        assert!(matches!(res, Err(RatesApiError::ApiError(m)) if m == "missing ZIL price"));
+
+        server.reset();
+        let _mock = serve(&mut server, path, 200, "not json").await;
+        let res = provider.fetch(&currencies(&["USD"])).await;
+        assert!(matches!(res, Err(RatesApiError::ParseResponseError(..))));
+    }
+
+    #[tokio::test]
+    async fn test_metals_provider() {
+        let mut server = Server::new_async().await;
+        let provider = Metals::new(Client::new(), &server.url(), "AED", Some("key".into()));
+        let path = "/v1/latest";
+        let mock = server
+            .mock("GET", path)
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("api_key".into(), "key".into()),
+                Matcher::UrlEncoded("currency".into(), "AED".into()),
+                Matcher::UrlEncoded("unit".into(), "g".into()),
+            ]))
+            .with_body(
+                json!({
+                    "status": "success",
+                    "currency": "AED",
+                    "currencies": { "AED": 1.0, "ZAR": 0.2 },
+                    "metals": { "gold": 378.2 }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let res = provider
+            .fetch(&currencies(&["AED", "ZAR", "XAU", "USD"]))
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(provider.quoting(), Quoting::Cross);
+        assert_eq!(res.get("zar"), Some(&0.2));
+        assert_eq!(res.get("xau"), Some(&378.2));
+        assert_eq!(res.len(), 2);
+
+        server.reset();
+        let _mock = serve(
+            &mut server,
+            path,
+            401,
+            r#"{"status":"failure","error_code":1101,"error_message":"Invalid key."}"#,
+        )
+        .await;
+        let res = provider.fetch(&currencies(&["ZAR"])).await;
+        assert!(matches!(res, Err(RatesApiError::InvalidApiKey(m)) if m == "Invalid key."));
+
+        server.reset();
+        let _mock = serve(
+            &mut server,
+            path,
+            200,
+            r#"{"status":"failure","error_code":1203,"error_message":"Quota exceeded."}"#,
+        )
+        .await;
+        let res = provider.fetch(&currencies(&["ZAR"])).await;
+        assert!(matches!(res, Err(RatesApiError::ApiError(m)) if m == "Quota exceeded."));
+
+        server.reset();
+        let _mock = serve(&mut server, path, 200, "{\"metals\": [").await;
+        let res = provider.fetch(&currencies(&["ZAR"])).await;
+        assert!(matches!(res, Err(RatesApiError::ParseResponseError(..))));
+
+        let keyless = Metals::new(Client::new(), &server.url(), "AED", None);
+        let res = keyless.fetch(&currencies(&["ZAR"])).await;
+        assert!(matches!(res, Err(RatesApiError::EnvVar(_))));
+
+        // Transport errors are logged, so they must not carry the key.
+        let unreachable = Metals::new(
+            Client::new(),
+            "http://127.0.0.1:1",
+            "AED",
+            Some("secret".into()),
+        );
+        let res = unreachable.fetch(&currencies(&["ZAR"])).await;
+        assert!(matches!(&res, Err(RatesApiError::Reqwest(_))));
+        assert!(!res.unwrap_err().to_string().contains("secret"));
+    }
+
     // #[tokio::test]
     // async fn test_coingecko_rates() {
     //     let tokens = ["BNB", "ETH", "USDT", "USDC", "JPY", "RUB", "EKO"];
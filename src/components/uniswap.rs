@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use alloy::{
     primitives::{address, Address, U256},
@@ -10,6 +10,7 @@ use serde_json::json;
 use thiserror::Error;
 
 use super::tokens::Token;
+use crate::metrics;
 
 pub const URLS: [&str; 5] = [
     "https://cloudflare-eth.com",
@@ -68,18 +69,35 @@ async fn send_batch_request(
     urls: &[&str],
     requests: &[serde_json::Value],
 ) -> Result<Vec<serde_json::Value>, UniswapDexError> {
-    for url in urls {
+    for (i, url) in urls.iter().enumerate() {
+        let started = Instant::now();
+        let provider = format!("uniswap[{}]", i);
+
         match client.post(*url).json(requests).send().await {
             Ok(response) => {
                 if response.status().is_success() {
-                    let responses: Vec<serde_json::Value> =
-                        response.json().await.map_err(UniswapDexError::Reqwest)?;
-                    if responses.iter().all(|r| r.get("result").is_some()) {
+                    let responses: Vec<serde_json::Value> = match response.json().await {
+                        Ok(responses) => responses,
+                        Err(e) => {
+                            metrics::observe_upstream(&provider, false, started.elapsed());
+
+                            return Err(UniswapDexError::Reqwest(e));
+                        }
+                    };
+                    let success = responses.iter().all(|r| r.get("result").is_some());
+
+                    metrics::observe_upstream(&provider, success, started.elapsed());
+
+                    if success {
                         return Ok(responses);
                     }
+                } else {
+                    metrics::observe_upstream(&provider, false, started.elapsed());
                 }
             }
-            Err(_e) => {}
+            Err(_e) => {
+                metrics::observe_upstream(&provider, false, started.elapsed());
+            }
         }
     }
     Err(UniswapDexError::ApiError(
@@ -87,7 +105,10 @@ async fn send_batch_request(
     ))
 }
 
-pub async fn get_token_prices_in_eth(tokens: &mut [Token]) -> Result<(), UniswapDexError> {
+pub async fn get_token_prices_in_eth(
+    tokens: &mut [Token],
+    urls: &[&str],
+) -> Result<(), UniswapDexError> {
     let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
 
     let mut batch_requests = Vec::with_capacity(tokens.len());
@@ -101,7 +122,7 @@ pub async fn get_token_prices_in_eth(tokens: &mut [Token]) -> Result<(), Uniswap
         batch_requests.push(request);
     }
 
-    let responses = send_batch_request(&client, &URLS, &batch_requests).await?;
+    let responses = send_batch_request(&client, urls, &batch_requests).await?;
 
     let mut pair_addresses = vec![Address::ZERO; tokens.len()];
     for resp in responses {
@@ -141,7 +162,7 @@ pub async fn get_token_prices_in_eth(tokens: &mut [Token]) -> Result<(), Uniswap
         }
     }
 
-    let responses_2 = send_batch_request(&client, &URLS, &batch_requests_2).await?;
+    let responses_2 = send_batch_request(&client, urls, &batch_requests_2).await?;
 
     let mut reserves = vec![None; tokens.len()];
     let mut token0s = vec![None; tokens.len()];
@@ -246,7 +267,7 @@ mod tests {
             },
         ];
 
-        get_token_prices_in_eth(&mut tokens)
+        get_token_prices_in_eth(&mut tokens, &URLS)
             .await
             .expect("Failed to fetch token prices");
 
@@ -0,0 +1 @@
+pub const AUDIT_KEY: &str = "AUDIT";
@@ -0,0 +1,7 @@
+pub const CANDLES_KEY: &str = "CANDLES";
+/// Currencies candles are kept in besides ZIL for tokens.
+pub const CANDLES_CURRENCIES: [&str; 1] = ["USD"];
+/// 5 minute candles are kept for a week and hourly ones for 90 days; daily
+/// and weekly candles are kept forever.
+pub const CANDLES_5M_RETENTION_SECS: u64 = 7 * 24 * 3600;
+pub const CANDLES_1H_RETENTION_SECS: u64 = 90 * 24 * 3600;
@@ -0,0 +1,8 @@
+pub const HISTORY_KEY: &str = "HISTORY";
+pub const HISTORY_RAW_TREE: &str = "raw";
+pub const HISTORY_HOURLY_TREE: &str = "hourly";
+pub const HISTORY_DAILY_TREE: &str = "daily";
+/// Raw snapshots are kept for a day, hourly points for 90 days and daily
+/// points forever.
+pub const HISTORY_RAW_RETENTION_SECS: u64 = 24 * 3600;
+pub const HISTORY_HOURLY_RETENTION_SECS: u64 = 90 * 24 * 3600;
@@ -12,3 +12,5 @@ pub const MIN_SCORE: u8 = 5;
 pub const CRYPTO_META_URL: &str =
     "https://raw.githubusercontent.com/ViewBlock/cryptometa/master/src/full.json";
 pub const META_KEY: &str = "META";
+pub const META_REVISIONS_TREE: &str = "revisions";
+pub const META_PINS_TREE: &str = "pins";
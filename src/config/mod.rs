@@ -1,7 +1,11 @@
+pub mod audit;
 pub mod blockchain;
+pub mod candles;
 pub mod currencies;
 pub mod dex;
+pub mod history;
 pub mod meta;
 pub mod rates;
 pub mod server;
+pub mod settings;
 pub mod zilliqa;
@@ -1,3 +1,6 @@
 pub const BASE_CURRENCY: &str = "AED";
 pub const API_URL_COINGECKO: &str = "https://api.coingecko.com/api/v3/simple/price";
 pub const CRYPTOCOMPARE_TOKENS_LIMIT: usize = 50;
+pub const API_URL_CRYPTOCOMPARE: &str = "https://min-api.cryptocompare.com";
+pub const API_URL_METALS: &str = "https://api.metals.dev";
+pub const RATES_REQUEST_TIMEOUT_SECS: u64 = 10;
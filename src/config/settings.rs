@@ -0,0 +1,1001 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use toml::{Table, Value};
+
+use super::{
+    candles::CANDLES_CURRENCIES,
+    currencies::CURRENCIES,
+    dex::DEX,
+    meta::{CRYPTO_META_URL, MIN_SCORE, TOKENS_EXCEPTIONS},
+    rates::{API_URL_COINGECKO, API_URL_CRYPTOCOMPARE, API_URL_METALS, BASE_CURRENCY},
+    zilliqa::PROVIDERS,
+};
+
+pub const CONFIG_PATH_ENV: &str = "CONFIG_PATH";
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+pub const ENV_PREFIX: &str = "DATA_RS__";
+
+/// Legacy environment variables kept for compatibility with existing deployments.
+const ENV_ALIASES: [(&str, &str); 3] = [
+    ("DB_PATH", "database.path"),
+    ("PORT", "server.port"),
+    ("ACCESS_TOKEN", "server.access_token"),
+];
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Cannot read config file {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+
+    #[error("Cannot parse config file {0}: {1}")]
+    Parse(PathBuf, String),
+
+    #[error("Invalid value for key `{0}`: {1}")]
+    Invalid(String, String),
+
+    #[error("Env var {0} (key `{1}`): {2}")]
+    Env(String, String, String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// Port of the default `127.0.0.1` listener, used when `listeners` is empty.
+    pub port: u16,
+    /// Legacy admin key limited to `tokens:write`; prefer `[[admin.keys]]`.
+    pub access_token: String,
+    pub shutdown_timeout_secs: u64,
+    /// Responses smaller than this are sent uncompressed; 0 disables compression.
+    pub compression_min_bytes: usize,
+    /// Compressed bodies kept for responses that carry an ETag.
+    pub compression_cache_entries: usize,
+    pub listeners: Vec<ListenerSettings>,
+}
+
+/// One socket to serve on: a TCP `address` (IPv4 or IPv6) or a `unix` socket path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ListenerSettings {
+    pub address: Option<String>,
+    pub unix: Option<String>,
+    pub http1: bool,
+    pub http2: bool,
+    /// Terminates TLS on this TCP listener when set.
+    pub tls: Option<TlsSettings>,
+}
+
+/// PEM certificate chain and private key, re-read on SIGHUP.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    pub cert: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseSettings {
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZilliqaSettings {
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetaSettings {
+    pub url: String,
+    pub min_score: u8,
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+    pub max_backoff_secs: u64,
+    /// Pairs of `[listed_bech32, replacement_bech32]`.
+    pub exceptions: Vec<[String; 2]>,
+    /// Revisions of the token list kept for rollback.
+    pub max_revisions: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DexSettings {
+    pub contract: String,
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+    pub max_backoff_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RatesSettings {
+    pub coingecko_url: String,
+    pub cryptocompare_url: String,
+    pub metals_url: String,
+    /// Currency the cross-rates of CryptoCompare and metals.dev are quoted in.
+    pub base_currency: String,
+    pub currencies: Vec<String>,
+    /// Sources aggregated into each rate, by weighted median.
+    pub sources: Vec<RateSourceSettings>,
+    pub interval_secs: u64,
+    pub jitter_secs: u64,
+    pub max_backoff_secs: u64,
+}
+
+/// Currencies of the OHLC candles, each also listed in `rates.currencies`.
+/// Token candles are kept in ZIL as well.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CandlesSettings {
+    pub currencies: Vec<String>,
+}
+
+/// Maximum age of each dataset before `/ready` reports the service as not ready.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSettings {
+    pub meta_max_age_secs: u64,
+    pub rates_max_age_secs: u64,
+    pub dex_max_age_secs: u64,
+}
+
+/// Token buckets per client: up to `burst` requests at once, refilled at
+/// `rate` requests per second.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    /// Proxies in front of the server that append to `X-Forwarded-For`;
+    /// 0 keys clients by the peer address.
+    pub trusted_hops: usize,
+    pub rate: f64,
+    pub burst: u32,
+    /// Routes with their own bucket and quota for anonymous clients.
+    pub routes: Vec<RouteLimit>,
+    /// Clients sending `X-API-Key` share one bucket with this quota.
+    pub keys: Vec<ApiKeyLimit>,
+}
+
+/// Quota of one route pattern as registered, e.g. `/api/v1/token/:id`;
+/// a `rate` of 0 leaves the route unlimited.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteLimit {
+    pub pattern: String,
+    pub rate: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKeyLimit {
+    pub name: String,
+    pub key: String,
+    pub rate: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateSource {
+    Coingecko,
+    Cryptocompare,
+    /// Fiat cross-rates only, anchored on the other sources; needs
+    /// `METALS_API_KEY`.
+    Metals,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateSourceSettings {
+    pub source: RateSource,
+    pub weight: f64,
+}
+
+/// Permission an admin key may hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "tokens:write")]
+    TokensWrite,
+    #[serde(rename = "admin:refresh")]
+    AdminRefresh,
+    #[serde(rename = "audit:read")]
+    AuditRead,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminSettings {
+    pub keys: Vec<AdminKeySettings>,
+}
+
+/// Only the SHA-256 of each secret is configured, e.g. the output of
+/// `printf %s "$SECRET" | sha256sum`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdminKeySettings {
+    /// Names the key in logs; never the secret.
+    pub id: String,
+    pub sha256: String,
+    pub scopes: Vec<Scope>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub server: ServerSettings,
+    pub database: DatabaseSettings,
+    pub zilliqa: ZilliqaSettings,
+    pub meta: MetaSettings,
+    pub dex: DexSettings,
+    pub rates: RatesSettings,
+    pub candles: CandlesSettings,
+    pub health: HealthSettings,
+    pub rate_limit: RateLimitSettings,
+    pub admin: AdminSettings,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            port: 0,
+            access_token: String::new(),
+            shutdown_timeout_secs: 30,
+            compression_min_bytes: 1024,
+            compression_cache_entries: 64,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+impl Default for ListenerSettings {
+    fn default() -> Self {
+        ListenerSettings {
+            address: None,
+            unix: None,
+            http1: true,
+            http2: true,
+            tls: None,
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn listeners(&self) -> Vec<ListenerSettings> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+
+        vec![ListenerSettings {
+            address: Some(SocketAddr::from(([127, 0, 0, 1], self.port)).to_string()),
+            ..Default::default()
+        }]
+    }
+}
+
+impl Default for ZilliqaSettings {
+    fn default() -> Self {
+        ZilliqaSettings {
+            providers: PROVIDERS.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+impl Default for MetaSettings {
+    fn default() -> Self {
+        MetaSettings {
+            url: CRYPTO_META_URL.to_string(),
+            min_score: MIN_SCORE,
+            interval_secs: 50,
+            jitter_secs: 5,
+            max_backoff_secs: 600,
+            exceptions: TOKENS_EXCEPTIONS
+                .iter()
+                .map(|[from, to]| [from.to_string(), to.to_string()])
+                .collect(),
+            max_revisions: 1000,
+        }
+    }
+}
+
+impl Default for DexSettings {
+    fn default() -> Self {
+        DexSettings {
+            contract: DEX.to_string(),
+            interval_secs: 20,
+            jitter_secs: 2,
+            max_backoff_secs: 300,
+        }
+    }
+}
+
+impl Default for RatesSettings {
+    fn default() -> Self {
+        RatesSettings {
+            coingecko_url: API_URL_COINGECKO.to_string(),
+            cryptocompare_url: API_URL_CRYPTOCOMPARE.to_string(),
+            metals_url: API_URL_METALS.to_string(),
+            base_currency: BASE_CURRENCY.to_string(),
+            currencies: CURRENCIES.iter().map(ToString::to_string).collect(),
+            sources: RateSource::ALL
+                .iter()
+                .map(|&source| RateSourceSettings {
+                    source,
+                    weight: 1.0,
+                })
+                .collect(),
+            interval_secs: 20,
+            jitter_secs: 2,
+            max_backoff_secs: 300,
+        }
+    }
+}
+
+impl Default for CandlesSettings {
+    fn default() -> Self {
+        CandlesSettings {
+            currencies: CANDLES_CURRENCIES.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        HealthSettings {
+            meta_max_age_secs: 600,
+            rates_max_age_secs: 120,
+            dex_max_age_secs: 120,
+        }
+    }
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::TokensWrite, Scope::AdminRefresh, Scope::AuditRead];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::TokensWrite => "tokens:write",
+            Scope::AdminRefresh => "admin:refresh",
+            Scope::AuditRead => "audit:read",
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl RateSource {
+    pub const ALL: [RateSource; 3] = [
+        RateSource::Coingecko,
+        RateSource::Cryptocompare,
+        RateSource::Metals,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateSource::Coingecko => "coingecko",
+            RateSource::Cryptocompare => "cryptocompare",
+            RateSource::Metals => "metals",
+        }
+    }
+}
+
+impl std::fmt::Display for RateSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        RateLimitSettings {
+            enabled: true,
+            trusted_hops: 0,
+            rate: 20.0,
+            burst: 40,
+            routes: Vec::new(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Loads settings from the file named by `CONFIG_PATH` (or `config.toml`
+    /// when present), applies env overrides and validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = std::env::var(CONFIG_PATH_ENV).ok().map(PathBuf::from);
+        let content = match &path {
+            Some(path) => Some(read_file(path)?),
+            None => {
+                let path = Path::new(DEFAULT_CONFIG_PATH);
+
+                if path.exists() {
+                    Some(read_file(path)?)
+                } else {
+                    None
+                }
+            }
+        };
+        let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        Settings::from_sources(content.as_deref(), &path, std::env::vars())
+    }
+
+    pub fn from_sources(
+        content: Option<&str>,
+        path: &Path,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        let mut table = Table::try_from(Settings::default())
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?;
+
+        if let Some(content) = content {
+            let file: Table = toml::from_str(content)
+                .map_err(|e| ConfigError::Parse(path.to_path_buf(), e.to_string()))?;
+
+            merge(&mut table, file);
+        }
+
+        for (name, raw) in vars {
+            let key = match ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+                Some((_, key)) => key.to_string(),
+                None => match name.strip_prefix(ENV_PREFIX) {
+                    Some(rest) => rest.to_lowercase().replace("__", "."),
+                    None => continue,
+                },
+            };
+
+            apply_override(&mut table, &key, &raw)
+                .map_err(|reason| ConfigError::Env(name.clone(), key, reason))?;
+        }
+
+        let settings: Settings = serde_path_to_error::deserialize(Value::Table(table))
+            .map_err(|e| ConfigError::Invalid(e.path().to_string(), e.inner().to_string()))?;
+
+        settings.validate()?;
+
+        Ok(settings)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key: &str, reason: &str| {
+            Err(ConfigError::Invalid(key.to_string(), reason.to_string()))
+        };
+
+        if self.server.listeners.is_empty() && self.server.port == 0 {
+            return invalid("server.port", "is required and must be non-zero");
+        }
+        for (i, listener) in self.server.listeners.iter().enumerate() {
+            let key = |field: &str| format!("server.listeners[{}].{}", i, field);
+
+            match (&listener.address, &listener.unix) {
+                (Some(address), None) => {
+                    if address.parse::<SocketAddr>().is_err() {
+                        return invalid(
+                            &key("address"),
+                            &format!("`{}` is not an ip:port address", address),
+                        );
+                    }
+                }
+                (None, Some(path)) => {
+                    if cfg!(not(unix)) || path.is_empty() {
+                        return invalid(&key("unix"), "unix sockets are not supported here");
+                    }
+                    if listener.tls.is_some() {
+                        return invalid(&key("tls"), "is only supported on tcp listeners");
+                    }
+                }
+                _ => return invalid(&key("address"), "set exactly one of address or unix"),
+            }
+            if !listener.http1 && !listener.http2 {
+                return invalid(&key("http1"), "enable at least one of http1 or http2");
+            }
+            if let Some(tls) = &listener.tls {
+                if tls.cert.is_empty() {
+                    return invalid(&key("tls.cert"), "is required");
+                }
+                if tls.key.is_empty() {
+                    return invalid(&key("tls.key"), "is required");
+                }
+            }
+        }
+        if self.server.access_token.is_empty() && self.admin.keys.is_empty() {
+            return invalid(
+                "admin.keys",
+                "at least one admin key (or server.access_token) is required",
+            );
+        }
+        let legacy_digest = (!self.server.access_token.is_empty())
+            .then(|| hex::encode(Sha256::digest(&self.server.access_token)));
+
+        for (i, admin_key) in self.admin.keys.iter().enumerate() {
+            let key = |field: &str| format!("admin.keys[{}].{}", i, field);
+            let earlier = &self.admin.keys[..i];
+
+            if admin_key.id.is_empty() || earlier.iter().any(|k| k.id == admin_key.id) {
+                return invalid(&key("id"), "must be non-empty and unique");
+            }
+            if admin_key.sha256.len() != 64 || hex::decode(&admin_key.sha256).is_err() {
+                return invalid(&key("sha256"), "must be a hex SHA-256 digest");
+            }
+            // A shared secret would authenticate as whichever key matched.
+            if earlier
+                .iter()
+                .map(|k| k.sha256.to_lowercase())
+                .chain(legacy_digest.clone())
+                .any(|d| d == admin_key.sha256.to_lowercase())
+            {
+                return invalid(&key("sha256"), "is the digest of another key");
+            }
+            if admin_key.scopes.is_empty() {
+                return invalid(&key("scopes"), "at least one scope is required");
+            }
+        }
+        if self.database.path.is_empty() {
+            return invalid("database.path", "is required");
+        }
+        if self.zilliqa.providers.is_empty() {
+            return invalid("zilliqa.providers", "at least one provider is required");
+        }
+        if let Some(url) = self
+            .zilliqa
+            .providers
+            .iter()
+            .find(|u| url::Url::parse(u).is_err())
+        {
+            return invalid(
+                "zilliqa.providers",
+                &format!("`{}` is not a valid url", url),
+            );
+        }
+        for (key, url) in [
+            ("meta.url", &self.meta.url),
+            ("rates.coingecko_url", &self.rates.coingecko_url),
+            ("rates.cryptocompare_url", &self.rates.cryptocompare_url),
+            ("rates.metals_url", &self.rates.metals_url),
+        ] {
+            if url::Url::parse(url).is_err() {
+                return invalid(key, &format!("`{}` is not a valid url", url));
+            }
+        }
+        if self.meta.max_revisions == 0 {
+            return invalid("meta.max_revisions", "must be greater than zero");
+        }
+        if self.dex.contract.len() != 40 || hex::decode(&self.dex.contract).is_err() {
+            return invalid("dex.contract", "must be a 20 byte hex address without 0x");
+        }
+        if self.rates.base_currency.is_empty() {
+            return invalid("rates.base_currency", "is required");
+        }
+        if self.rates.currencies.is_empty() {
+            return invalid("rates.currencies", "at least one currency is required");
+        }
+        if self.rates.sources.is_empty() {
+            return invalid("rates.sources", "at least one source is required");
+        }
+        for (i, source) in self.rates.sources.iter().enumerate() {
+            if self.rates.sources[..i]
+                .iter()
+                .any(|s| s.source == source.source)
+            {
+                return invalid(
+                    &format!("rates.sources[{}].source", i),
+                    &format!("`{}` is listed twice", source.source),
+                );
+            }
+            if !(source.weight > 0.0 && source.weight.is_finite()) {
+                return invalid(
+                    &format!("rates.sources[{}].weight", i),
+                    "must be greater than zero",
+                );
+            }
+        }
+        for (i, currency) in self.candles.currencies.iter().enumerate() {
+            if !self
+                .rates
+                .currencies
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(currency))
+            {
+                return invalid(
+                    &format!("candles.currencies[{}]", i),
+                    &format!("`{}` is not in rates.currencies", currency),
+                );
+            }
+        }
+        for (section, interval, max_backoff) in [
+            ("meta", self.meta.interval_secs, self.meta.max_backoff_secs),
+            ("dex", self.dex.interval_secs, self.dex.max_backoff_secs),
+            (
+                "rates",
+                self.rates.interval_secs,
+                self.rates.max_backoff_secs,
+            ),
+        ] {
+            if interval == 0 {
+                return invalid(
+                    &format!("{}.interval_secs", section),
+                    "must be greater than zero",
+                );
+            }
+            if max_backoff < interval {
+                return invalid(
+                    &format!("{}.max_backoff_secs", section),
+                    "must not be less than interval_secs",
+                );
+            }
+        }
+
+        self.validate_rate_limit()
+    }
+
+    fn validate_rate_limit(&self) -> Result<(), ConfigError> {
+        let invalid =
+            |key: String, reason: &str| Err(ConfigError::Invalid(key, reason.to_string()));
+        let limits = &self.rate_limit;
+
+        if !(limits.rate > 0.0 && limits.rate.is_finite()) {
+            return invalid("rate_limit.rate".to_string(), "must be greater than zero");
+        }
+        if limits.burst == 0 {
+            return invalid("rate_limit.burst".to_string(), "must be greater than zero");
+        }
+        for (i, route) in limits.routes.iter().enumerate() {
+            let key = |field: &str| format!("rate_limit.routes[{}].{}", i, field);
+
+            if !route.pattern.starts_with('/') {
+                return invalid(key("pattern"), "must start with /");
+            }
+            if !(route.rate >= 0.0 && route.rate.is_finite()) {
+                return invalid(key("rate"), "must not be negative");
+            }
+            if route.rate > 0.0 && route.burst == 0 {
+                return invalid(key("burst"), "must be greater than zero");
+            }
+        }
+        for (i, api_key) in limits.keys.iter().enumerate() {
+            let key = |field: &str| format!("rate_limit.keys[{}].{}", i, field);
+            let earlier = &limits.keys[..i];
+
+            if api_key.name.is_empty() || earlier.iter().any(|k| k.name == api_key.name) {
+                return invalid(key("name"), "must be non-empty and unique");
+            }
+            if api_key.key.is_empty() || earlier.iter().any(|k| k.key == api_key.key) {
+                return invalid(key("key"), "must be non-empty and unique");
+            }
+            if !(api_key.rate > 0.0 && api_key.rate.is_finite()) {
+                return invalid(key("rate"), "must be greater than zero");
+            }
+            if api_key.burst == 0 {
+                return invalid(key("burst"), "must be greater than zero");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ConfigError> {
+    std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))
+}
+
+fn merge(base: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(other)) => merge(base, other),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Sets `key` (dotted path) to `raw`, coercing it to the type of the current value.
+fn apply_override(table: &mut Table, key: &str, raw: &str) -> Result<(), String> {
+    let mut parts: Vec<&str> = key.split('.').collect();
+    let field = parts.pop().filter(|f| !f.is_empty()).ok_or("empty key")?;
+    let mut current = table;
+
+    for part in parts {
+        current = match current
+            .entry(part)
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(t) => t,
+            _ => return Err(format!("`{}` is not a section", part)),
+        };
+    }
+
+    let value = match current.get(field) {
+        Some(Value::Integer(_)) => Value::Integer(
+            raw.trim()
+                .parse()
+                .map_err(|_| format!("expected integer, got `{}`", raw))?,
+        ),
+        Some(Value::Float(_)) => Value::Float(
+            raw.trim()
+                .parse()
+                .map_err(|_| format!("expected float, got `{}`", raw))?,
+        ),
+        Some(Value::Boolean(_)) => Value::Boolean(
+            raw.trim()
+                .parse()
+                .map_err(|_| format!("expected true or false, got `{}`", raw))?,
+        ),
+        Some(Value::Array(_)) => {
+            let parsed: Result<Table, _> = toml::from_str(&format!("v = {}", raw));
+
+            match parsed.ok().and_then(|mut t| t.remove("v")) {
+                Some(value @ Value::Array(_)) => value,
+                _ => Value::Array(
+                    raw.split(',')
+                        .map(str::trim)
+                        .filter(|s| !s.is_empty())
+                        .map(|s| Value::String(s.to_string()))
+                        .collect(),
+                ),
+            }
+        }
+        Some(Value::Table(_)) => return Err("cannot override a whole section".to_string()),
+        _ => Value::String(raw.to_string()),
+    };
+
+    current.insert(field.to_string(), value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUIRED: &str = r#"
+        [server]
+        port = 8080
+        access_token = "secret"
+
+        [database]
+        path = "/tmp/data"
+    "#;
+
+    fn load(content: &str, vars: &[(&str, &str)]) -> Result<Settings, ConfigError> {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+
+        Settings::from_sources(Some(content), Path::new("test.toml"), vars)
+    }
+
+    #[test]
+    fn test_defaults_from_constants() {
+        let settings = load(REQUIRED, &[]).unwrap();
+
+        assert_eq!(settings.server.port, 8080);
+        assert_eq!(settings.dex.contract, DEX);
+        assert_eq!(settings.meta.min_score, MIN_SCORE);
+        assert_eq!(settings.rates.base_currency, BASE_CURRENCY);
+        assert_eq!(settings.zilliqa.providers.len(), PROVIDERS.len());
+        assert_eq!(settings.meta.exceptions.len(), TOKENS_EXCEPTIONS.len());
+    }
+
+    #[test]
+    fn test_file_overrides_defaults() {
+        let content = format!(
+            "{}\n[meta]\nmin_score = 7\n\n[zilliqa]\nproviders = [\"https://example.com\"]\n",
+            REQUIRED
+        );
+        let settings = load(&content, &[]).unwrap();
+
+        assert_eq!(settings.meta.min_score, 7);
+        assert_eq!(settings.meta.interval_secs, 50);
+        assert_eq!(settings.zilliqa.providers, vec!["https://example.com"]);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let settings = load(
+            REQUIRED,
+            &[
+                ("PORT", "9000"),
+                ("ACCESS_TOKEN", "777"),
+                ("DATA_RS__RATES__INTERVAL_SECS", "5"),
+                ("DATA_RS__ZILLIQA__PROVIDERS", "https://a.io, https://b.io"),
+                ("UNRELATED", "value"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(settings.server.port, 9000);
+        assert_eq!(settings.server.access_token, "777");
+        assert_eq!(settings.rates.interval_secs, 5);
+        assert_eq!(
+            settings.zilliqa.providers,
+            vec!["https://a.io", "https://b.io"]
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let err = load(REQUIRED, &[("PORT", "abc")]).unwrap_err();
+        assert!(err.to_string().contains("server.port"), "{}", err);
+
+        let err = load(&format!("{}\n[dex]\ncontract = \"0x1\"\n", REQUIRED), &[]).unwrap_err();
+        assert!(err.to_string().contains("dex.contract"), "{}", err);
+
+        let err = load(&format!("{}\n[meta]\nmin_scor = 1\n", REQUIRED), &[]).unwrap_err();
+        assert!(err.to_string().contains("meta.min_scor"), "{}", err);
+
+        let err = load(
+            &format!("{}\n[meta]\nmin_score = \"high\"\n", REQUIRED),
+            &[],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("meta.min_score"), "{}", err);
+
+        let err = load("[server]\nport = 1\n\n[database]\npath = \"db\"\n", &[]).unwrap_err();
+        assert!(err.to_string().contains("admin.keys"), "{}", err);
+
+        let err = load("[server]\nport = 1\naccess_token = \"x\"\n", &[]).unwrap_err();
+        assert!(err.to_string().contains("database.path"), "{}", err);
+    }
+
+    #[test]
+    fn test_listeners() {
+        let settings = load(REQUIRED, &[]).unwrap();
+        let listeners = settings.server.listeners();
+
+        assert_eq!(listeners.len(), 1);
+        assert_eq!(listeners[0].address.as_deref(), Some("127.0.0.1:8080"));
+
+        let content = format!(
+            "{}\n[[server.listeners]]\naddress = \"[::]:80\"\nhttp1 = false\n\n[[server.listeners]]\nunix = \"/run/data.sock\"\n",
+            REQUIRED
+        );
+        let settings = load(&content, &[]).unwrap();
+        let listeners = settings.server.listeners();
+
+        assert_eq!(listeners.len(), 2);
+        assert!(!listeners[0].http1 && listeners[0].http2);
+        assert_eq!(listeners[1].unix.as_deref(), Some("/run/data.sock"));
+
+        let content = format!(
+            "{}\n[[server.listeners]]\naddress = \"localhost\"\n",
+            REQUIRED
+        );
+        let err = load(&content, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("server.listeners[0].address"),
+            "{}",
+            err
+        );
+
+        let content = format!(
+            "{}\n[[server.listeners]]\naddress = \"[::]:443\"\ntls = {{ cert = \"a.crt\", key = \"a.key\" }}\n",
+            REQUIRED
+        );
+        let settings = load(&content, &[]).unwrap();
+        assert_eq!(
+            settings.server.listeners()[0].tls.as_ref().unwrap().key,
+            "a.key"
+        );
+
+        let content = format!(
+            "{}\n[[server.listeners]]\nunix = \"/run/data.sock\"\ntls = {{ cert = \"a.crt\", key = \"a.key\" }}\n",
+            REQUIRED
+        );
+        let err = load(&content, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("server.listeners[0].tls"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let content = format!(
+            "{}\n[rate_limit]\ntrusted_hops = 1\n\n[[rate_limit.routes]]\npattern = \"/api/v1/dex\"\nrate = 2\nburst = 5\n\n[[rate_limit.keys]]\nname = \"wallet\"\nkey = \"k1\"\nrate = 100\nburst = 200\n",
+            REQUIRED
+        );
+        let settings = load(&content, &[("DATA_RS__RATE_LIMIT__RATE", "2.5")]).unwrap();
+
+        assert_eq!(settings.rate_limit.trusted_hops, 1);
+        assert_eq!(settings.rate_limit.rate, 2.5);
+        assert_eq!(settings.rate_limit.routes[0].burst, 5);
+        assert_eq!(settings.rate_limit.keys[0].name, "wallet");
+
+        let content = format!(
+            "{}\n[[rate_limit.keys]]\nname = \"a\"\nkey = \"k\"\nrate = 1\nburst = 1\n\n[[rate_limit.keys]]\nname = \"b\"\nkey = \"k\"\nrate = 1\nburst = 1\n",
+            REQUIRED
+        );
+        let err = load(&content, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("rate_limit.keys[1].key"),
+            "{}",
+            err
+        );
+
+        let err = load(REQUIRED, &[("DATA_RS__RATE_LIMIT__BURST", "0")]).unwrap_err();
+        assert!(err.to_string().contains("rate_limit.burst"), "{}", err);
+    }
+
+    #[test]
+    fn test_rate_sources() {
+        let settings = load(REQUIRED, &[]).unwrap();
+        assert_eq!(settings.rates.sources.len(), RateSource::ALL.len());
+
+        let content = format!(
+            "{}\n[[rates.sources]]\nsource = \"coingecko\"\nweight = 2\n",
+            REQUIRED
+        );
+        let settings = load(&content, &[]).unwrap();
+        assert_eq!(settings.rates.sources[0].source, RateSource::Coingecko);
+        assert_eq!(settings.rates.sources.len(), 1);
+
+        let content = format!(
+            "{}\n[[rates.sources]]\nsource = \"metals\"\nweight = 0\n",
+            REQUIRED
+        );
+        let err = load(&content, &[]).unwrap_err();
+        assert!(
+            err.to_string().contains("rates.sources[0].weight"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_candles_currencies() {
+        let settings = load(REQUIRED, &[]).unwrap();
+        assert_eq!(settings.candles.currencies, vec!["USD"]);
+
+        let content = format!("{}\n[candles]\ncurrencies = [\"eur\", \"XYZ\"]\n", REQUIRED);
+        let err = load(&content, &[]).unwrap_err();
+        assert!(err.to_string().contains("candles.currencies[1]"), "{}", err);
+    }
+
+    #[test]
+    fn test_admin_keys() {
+        let digest = "a".repeat(64);
+        let content = format!(
+            "{}\n[[admin.keys]]\nid = \"ops\"\nsha256 = \"{}\"\nscopes = [\"tokens:write\", \"admin:refresh\"]\n",
+            REQUIRED, digest
+        );
+        let settings = load(&content, &[]).unwrap();
+
+        assert_eq!(settings.admin.keys[0].id, "ops");
+        assert_eq!(
+            settings.admin.keys[0].scopes,
+            vec![Scope::TokensWrite, Scope::AdminRefresh]
+        );
+
+        let err = load(&content.replace("admin:refresh", "admin:all"), &[]).unwrap_err();
+        assert!(err.to_string().contains("admin.keys"), "{}", err);
+
+        let err = load(&content.replace(&digest, "secret"), &[]).unwrap_err();
+        assert!(err.to_string().contains("admin.keys[0].sha256"), "{}", err);
+
+        let twice = format!(
+            "{}[[admin.keys]]\nid = \"backup\"\nsha256 = \"{}\"\nscopes = [\"audit:read\"]\n",
+            content,
+            digest.to_uppercase()
+        );
+        let err = load(&twice, &[]).unwrap_err();
+        assert!(err.to_string().contains("admin.keys[1].sha256"), "{}", err);
+
+        let legacy = hex::encode(Sha256::digest("legacy"));
+        let err = load(
+            &content.replace(&digest, &legacy),
+            &[("ACCESS_TOKEN", "legacy")],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("admin.keys[0].sha256"), "{}", err);
+    }
+}
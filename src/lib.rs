@@ -1,5 +1,7 @@
 pub mod components;
 pub mod config;
+pub mod metrics;
 pub mod models;
+pub mod scheduler;
 pub mod server;
 pub mod utils;
@@ -1,13 +1,26 @@
 use data_rs::{
-    models::{currencies::Currencies, dex::Dex, meta::Meta},
-    server::run_server,
-    utils::zilliqa::Zilliqa,
+    components::rates::providers,
+    config::settings::Settings,
+    models::{
+        audit::AuditLog, candles::Candles, currencies::Currencies, dex::Dex, history::RatesHistory,
+        meta::Meta,
+    },
+    scheduler::{
+        jobs::{DexJob, MetaJob, RatesJob},
+        Scheduler,
+    },
+    server::{
+        run_server, signal,
+        snapshot::{Snapshot, Snapshots},
+        state::{AppState, Series},
+    },
+    utils::{time::now_secs, zilliqa::Zilliqa},
 };
-use log::{error, LevelFilter};
+use log::{error, info, warn, LevelFilter};
 use simple_logger::SimpleLogger;
-use std::{sync::Arc, time::Duration};
-use tokio;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
 #[tokio::main]
 async fn main() {
@@ -17,98 +30,163 @@ async fn main() {
         .init()
         .unwrap();
 
-    let db_path = std::env::var("DB_PATH").expect("Incorrect DB_PATH env var");
-    let port: u16 = std::env::var("PORT")
-        .expect("ENV var PORT is required")
-        .parse()
-        .expect("ENV var PORT should be u16 number");
+    let settings = match Settings::load() {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            error!("config: {}", e);
 
-    let meta = Arc::new(RwLock::new(Meta::new(&db_path)));
-    let rates = Arc::new(RwLock::new(Currencies::new(&db_path)));
-    let dex = Arc::new(RwLock::new(Dex::new(&db_path)));
-
-    let meta_ref = Arc::clone(&meta);
-    let dex_ref = Arc::clone(&dex);
-    let meta_dex_ref = Arc::clone(&dex);
-    let rates_ref = Arc::clone(&rates);
+            std::process::exit(1);
+        }
+    };
+    let db_path = &settings.database.path;
+
+    let (meta, rates, dex) = (
+        Meta::new(db_path, settings.meta.max_revisions),
+        Currencies::new(db_path),
+        Dex::new(db_path),
+    );
+    let history = Arc::new(RatesHistory::new(db_path));
+    let candles = Arc::new(Candles::new(db_path, &settings.candles));
+
+    match candles.backfill(&history) {
+        Ok(0) => {}
+        Ok(replayed) => info!("candles: replayed {} rates history points", replayed),
+        Err(e) => warn!("candles: cannot backfill from history, error: {}", e),
+    }
+
+    let token_changes = candles
+        .token_changes(now_secs(), &meta.list, &dex.pools)
+        .unwrap_or_else(|e| {
+            warn!("candles: cannot load token changes, error: {}", e);
+
+            HashMap::new()
+        });
+    let snapshots = match Snapshot::build(
+        meta.list.clone(),
+        dex.pools.clone(),
+        rates.data.clone(),
+        token_changes,
+    ) {
+        Ok(snapshot) => Arc::new(Snapshots::new(snapshot)),
+        Err(e) => {
+            error!("snapshot: {}", e);
+
+            std::process::exit(1);
+        }
+    };
+    let audit = Arc::new(AuditLog::new(db_path));
+    let meta = Arc::new(RwLock::new(meta));
+    let rates = Arc::new(RwLock::new(rates));
+    let dex = Arc::new(RwLock::new(dex));
+    let zilliqa = Zilliqa::from(settings.zilliqa.providers.clone());
+
+    let meta_job = MetaJob {
+        meta: Arc::clone(&meta),
+        dex: Arc::clone(&dex),
+        snapshots: Arc::clone(&snapshots),
+        zilliqa: zilliqa.clone(),
+        settings: settings.meta.clone(),
+    };
+    let rates_job = RatesJob {
+        rates: Arc::clone(&rates),
+        history: Arc::clone(&history),
+        candles: Arc::clone(&candles),
+        snapshots: Arc::clone(&snapshots),
+        providers: providers(&settings.rates),
+        settings: settings.rates.clone(),
+    };
+    let dex_job = DexJob {
+        dex: Arc::clone(&dex),
+        candles: Arc::clone(&candles),
+        snapshots: Arc::clone(&snapshots),
+        zilliqa: zilliqa.clone(),
+        settings: settings.dex.clone(),
+    };
+    let (meta_schedule, rates_schedule, dex_schedule) = (
+        meta_job.schedule(),
+        rates_job.schedule(),
+        dex_job.schedule(),
+    );
+    let mut scheduler = Scheduler::new();
+
+    scheduler
+        .add(meta_job, meta_schedule)
+        .add(rates_job, rates_schedule)
+        .add(dex_job, dex_schedule);
+    let shutdown = CancellationToken::new();
+    let updaters = scheduler.spawn(shutdown.clone());
+    let state = AppState::new(
+        Arc::clone(&meta),
+        snapshots,
+        Arc::clone(&audit),
+        Series {
+            history: Arc::clone(&history),
+            candles: Arc::clone(&candles),
+        },
+        zilliqa,
+        Arc::clone(&settings),
+        Arc::new(scheduler),
+    );
+    let signal_token = shutdown.clone();
 
     tokio::task::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(50)).await;
-
-            let zil = Zilliqa::new();
-            let tokens = match Meta::get_meta_tokens().await {
-                Ok(tokens) => tokens,
-                Err(e) => {
-                    error!("github:meta: {:?}", e);
-
-                    continue;
-                }
-            };
-
-            let sorted = match Meta::sort_zilliqa_tokens(&tokens, &zil).await {
-                Ok(sorted) => sorted,
-                Err(e) => {
-                    error!("zilliqa node: {:?}", e);
-
-                    continue;
-                }
-            };
-
-            let mut unwarp_meta = meta_ref.write().await;
-
-            match unwarp_meta.update(tokens, sorted) {
-                Ok(_) => {
-                    let dex = meta_dex_ref.read().await;
-
-                    unwarp_meta.listed_tokens_update(&dex);
-                    unwarp_meta.write_db().unwrap(); // TODO: make Error hanlder.
-                }
-                Err(e) => {
-                    error!("tokens update: {:?}", e);
-
-                    continue;
-                }
-            };
-        }
+        signal::terminate().await;
+        signal_token.cancel();
     });
 
-    tokio::task::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(20)).await;
-
-            match Currencies::fetch_rates().await {
-                Ok(rates) => {
-                    rates_ref.write().await.update(rates).unwrap();
-                }
-                Err(e) => {
-                    error!("fetch rates error: {:?}", e);
-                }
-            };
+    let timeout = Duration::from_secs(settings.server.shutdown_timeout_secs);
+    let server = async {
+        if let Err(e) = run_server(state, shutdown.clone()).await {
+            error!("server: {}", e);
         }
-    });
 
-    tokio::task::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_secs(20)).await;
-
-            let zil = Zilliqa::new();
-            match Dex::get_pools(&zil).await {
-                Ok(pools) => {
-                    dex_ref.write().await.update(pools).unwrap();
-                }
-                Err(e) => {
-                    error!("fetch rates error: {:?}", e);
-                }
-            };
+        shutdown.cancel();
+    };
+
+    // The updaters stop while the connections drain, so shutting down takes
+    // at most `shutdown_timeout_secs` overall.
+    tokio::join!(server, stop_updaters(updaters, shutdown.clone(), timeout));
+
+    for (name, result) in [
+        ("meta", meta.read().await.flush()),
+        ("dex", dex.read().await.flush()),
+        ("rates", rates.read().await.flush()),
+        ("audit", audit.flush()),
+        ("history", history.flush()),
+        ("candles", candles.flush()),
+    ] {
+        match result {
+            Ok(bytes) => info!("{}: flushed {} bytes", name, bytes),
+            Err(e) => error!("{}: flush failed: {}", name, e),
         }
-    });
+    }
+}
+
+/// Waits for `shutdown`, then up to `timeout` for the updaters to finish
+/// their current run, aborting them after that.
+async fn stop_updaters(
+    updaters: Vec<tokio::task::JoinHandle<()>>,
+    shutdown: CancellationToken,
+    timeout: Duration,
+) {
+    shutdown.cancelled().await;
 
-    let meta_ref0 = Arc::clone(&meta);
-    let dex_ref0 = Arc::clone(&dex);
-    let rates_ref0 = Arc::clone(&rates);
+    let aborts: Vec<_> = updaters.iter().map(|h| h.abort_handle()).collect();
 
-    run_server(&meta_ref0, &dex_ref0, &rates_ref0, port)
+    if tokio::time::timeout(timeout, join_updaters(updaters))
         .await
-        .unwrap();
+        .is_err()
+    {
+        warn!("updaters did not stop within {:?}, aborting", timeout);
+
+        aborts.iter().for_each(|a| a.abort());
+    }
+}
+
+async fn join_updaters(handles: Vec<tokio::task::JoinHandle<()>>) {
+    for handle in handles {
+        if let Err(e) = handle.await {
+            error!("updater task failed: {}", e);
+        }
+    }
 }
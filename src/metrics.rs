@@ -0,0 +1,178 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::{sync::LazyLock, time::Duration};
+
+pub static REGISTRY: LazyLock<Registry> = LazyLock::new(|| {
+    let registry =
+        Registry::new_custom(Some("data_rs".to_string()), None).expect("valid metrics namespace");
+
+    registry
+        .register(Box::new(HTTP_REQUESTS.clone()))
+        .expect("register http_requests_total");
+    registry
+        .register(Box::new(HTTP_DURATION.clone()))
+        .expect("register http_request_duration_seconds");
+    registry
+        .register(Box::new(UPSTREAM_REQUESTS.clone()))
+        .expect("register upstream_requests_total");
+    registry
+        .register(Box::new(UPSTREAM_DURATION.clone()))
+        .expect("register upstream_request_duration_seconds");
+    registry
+        .register(Box::new(JOB_RUNS.clone()))
+        .expect("register job_runs_total");
+    registry
+        .register(Box::new(JOB_DURATION.clone()))
+        .expect("register job_duration_seconds");
+    registry
+        .register(Box::new(DATASET_ITEMS.clone()))
+        .expect("register dataset_items");
+
+    registry
+});
+
+pub static HTTP_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    IntCounterVec::new(
+        Opts::new("http_requests_total", "HTTP requests by route and status"),
+        &["route", "method", "status"],
+    )
+    .expect("valid metric")
+});
+
+pub static HTTP_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new(
+            "http_request_duration_seconds",
+            "HTTP request latency by route",
+        )
+        .buckets(vec![
+            0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+        ]),
+        &["route", "method"],
+    )
+    .expect("valid metric")
+});
+
+pub static UPSTREAM_REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    IntCounterVec::new(
+        Opts::new(
+            "upstream_requests_total",
+            "Calls to upstream providers by outcome",
+        ),
+        &["provider", "outcome"],
+    )
+    .expect("valid metric")
+});
+
+pub static UPSTREAM_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new(
+            "upstream_request_duration_seconds",
+            "Latency of calls to upstream providers",
+        )
+        .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+        &["provider"],
+    )
+    .expect("valid metric")
+});
+
+pub static JOB_RUNS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    IntCounterVec::new(
+        Opts::new("job_runs_total", "Background updater runs by outcome"),
+        &["job", "outcome"],
+    )
+    .expect("valid metric")
+});
+
+pub static JOB_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    HistogramVec::new(
+        HistogramOpts::new("job_duration_seconds", "Background updater run duration")
+            .buckets(vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]),
+        &["job"],
+    )
+    .expect("valid metric")
+});
+
+pub static DATASET_ITEMS: LazyLock<IntGaugeVec> = LazyLock::new(|| {
+    IntGaugeVec::new(
+        Opts::new(
+            "dataset_items",
+            "Number of tokens, pools and currencies held",
+        ),
+        &["dataset"],
+    )
+    .expect("valid metric")
+});
+
+pub fn outcome(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "error"
+    }
+}
+
+/// Records a call to `provider`, a name such as the rate source or the
+/// config key of the node, never its url, which may carry credentials.
+pub fn observe_upstream(provider: &str, success: bool, elapsed: Duration) {
+    UPSTREAM_REQUESTS
+        .with_label_values(&[provider, outcome(success)])
+        .inc();
+    UPSTREAM_DURATION
+        .with_label_values(&[provider])
+        .observe(elapsed.as_secs_f64());
+}
+
+pub fn observe_job(job: &str, success: bool, elapsed: Duration) {
+    JOB_RUNS.with_label_values(&[job, outcome(success)]).inc();
+    JOB_DURATION
+        .with_label_values(&[job])
+        .observe(elapsed.as_secs_f64());
+}
+
+pub fn observe_request(route: &str, method: &str, status: u16, elapsed: Duration) {
+    HTTP_REQUESTS
+        .with_label_values(&[route, method, &status.to_string()])
+        .inc();
+    HTTP_DURATION
+        .with_label_values(&[route, method])
+        .observe(elapsed.as_secs_f64());
+}
+
+pub fn set_dataset_items(dataset: &str, items: usize) {
+    DATASET_ITEMS
+        .with_label_values(&[dataset])
+        .set(items as i64);
+}
+
+/// Renders every registered metric in the Prometheus text format.
+pub fn gather() -> Result<String, prometheus::Error> {
+    let mut buffer = Vec::new();
+
+    TextEncoder::new().encode(&REGISTRY.gather(), &mut buffer)?;
+
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gather_renders_text_format() {
+        observe_request("/api/v1/rates", "GET", 200, Duration::from_millis(3));
+        observe_upstream("metals", false, Duration::from_millis(30));
+        set_dataset_items("pools", 42);
+
+        let text = gather().unwrap();
+
+        assert!(text.contains(
+            "data_rs_http_requests_total{method=\"GET\",route=\"/api/v1/rates\",status=\"200\"}"
+        ));
+        assert!(
+            text.contains("data_rs_upstream_requests_total{outcome=\"error\",provider=\"metals\"}")
+        );
+        assert!(text.contains("data_rs_dataset_items{dataset=\"pools\"} 42"));
+    }
+}
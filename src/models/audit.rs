@@ -0,0 +1,250 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sled::{Batch, Db};
+use std::io::Error;
+use utoipa::ToSchema;
+
+use crate::{config::audit::AUDIT_KEY, utils::time::now_secs};
+
+/// Append-only trail of admin edits, ordered by time.
+///
+/// Keys are the big-endian timestamp followed by the big-endian entry id,
+/// so time ranges are plain range scans.
+#[derive(Debug)]
+pub struct AuditLog {
+    db: Db,
+}
+
+/// One changed field of one token.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: u64,
+    /// Unix seconds.
+    pub timestamp: u64,
+    /// Id of the admin key that made the change.
+    pub key_id: String,
+    /// Lowercase base16 address of the token, with `0x`.
+    pub token: String,
+    pub field: String,
+    #[schema(value_type = Object)]
+    pub old_value: Value,
+    #[schema(value_type = Object)]
+    pub new_value: Value,
+}
+
+/// A field change to record; the log assigns id and timestamp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub field: String,
+    pub old_value: Value,
+    pub new_value: Value,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub token: Option<String>,
+    /// Inclusive bounds in unix seconds.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    pub limit: usize,
+}
+
+impl AuditLog {
+    pub fn new(db_path: &str) -> Self {
+        let db =
+            sled::open(format!("{}/{}", db_path, AUDIT_KEY)).expect("Cannot open audit database.");
+
+        info!("AUDIT: {} entries", db.len());
+
+        AuditLog { db }
+    }
+
+    /// Stores every change of `token` made with `key_id` at once.
+    pub fn append(
+        &self,
+        key_id: &str,
+        token: &str,
+        changes: Vec<Change>,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        self.append_all(key_id, vec![(token.to_string(), changes)])
+    }
+
+    /// Stores the changes of several tokens made with `key_id` at once.
+    pub fn append_all(
+        &self,
+        key_id: &str,
+        changes: Vec<(String, Vec<Change>)>,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        self.append_at(now_secs(), key_id, changes)
+    }
+
+    fn append_at(
+        &self,
+        timestamp: u64,
+        key_id: &str,
+        changes: Vec<(String, Vec<Change>)>,
+    ) -> Result<Vec<AuditEntry>, Error> {
+        let mut batch = Batch::default();
+        let mut entries = Vec::new();
+
+        for (token, changes) in changes {
+            for change in changes {
+                let entry = AuditEntry {
+                    id: self.db.generate_id()?,
+                    timestamp,
+                    key_id: key_id.to_string(),
+                    token: token_key(&token),
+                    field: change.field,
+                    old_value: change.old_value,
+                    new_value: change.new_value,
+                };
+
+                batch.insert(&key(entry.timestamp, entry.id), serde_json::to_vec(&entry)?);
+                entries.push(entry);
+            }
+        }
+
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+
+        Ok(entries)
+    }
+
+    /// Matching entries, newest first.
+    pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, Error> {
+        let from = key(filter.from.unwrap_or(0), 0);
+        let to = key(filter.to.unwrap_or(u64::MAX), u64::MAX);
+        let token = filter.token.as_deref().map(token_key);
+        let mut entries = Vec::new();
+
+        for item in self.db.range(from..=to).rev() {
+            if entries.len() >= filter.limit {
+                break;
+            }
+
+            let (_, value) = item?;
+            let entry: AuditEntry = serde_json::from_slice(&value)?;
+
+            if token.as_ref().is_none_or(|t| *t == entry.token) {
+                entries.push(entry);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    pub fn flush(&self) -> Result<usize, Error> {
+        Ok(self.db.flush()?)
+    }
+}
+
+/// `token` lowercased, with `0x` whether it came with `0x`, `0X` or
+/// neither, so every spelling of an address finds the same entries.
+fn token_key(token: &str) -> String {
+    let token = token.to_lowercase();
+
+    format!("0x{}", token.strip_prefix("0x").unwrap_or(&token))
+}
+
+fn key(timestamp: u64, id: u64) -> [u8; 16] {
+    let mut key = [0; 16];
+
+    key[..8].copy_from_slice(&timestamp.to_be_bytes());
+    key[8..].copy_from_slice(&id.to_be_bytes());
+
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn change(field: &str, old_value: Value, new_value: Value) -> Change {
+        Change {
+            field: field.to_string(),
+            old_value,
+            new_value,
+        }
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = AuditLog::new(dir.path().to_str().unwrap());
+
+        log.append_at(
+            100,
+            "ops",
+            vec![(
+                "0XAA".to_string(),
+                vec![change("listed", json!(false), json!(true))],
+            )],
+        )
+        .unwrap();
+        log.append_at(
+            200,
+            "ops",
+            vec![(
+                "0xbb".to_string(),
+                vec![
+                    change("status", json!(1), json!(0)),
+                    change("symbol", json!("OLD"), json!("NEW")),
+                ],
+            )],
+        )
+        .unwrap();
+        log.append_at(
+            300,
+            "ci",
+            vec![("aa".to_string(), vec![change("score", json!(5), json!(7))])],
+        )
+        .unwrap();
+
+        let all = log
+            .query(&AuditFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            all.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+            vec![300, 200, 200, 100]
+        );
+
+        let token = log
+            .query(&AuditFilter {
+                token: Some("0xAA".to_string()),
+                limit: 10,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(token.len(), 2);
+        assert_eq!(token[0].token, "0xaa");
+        assert_eq!(token[1].field, "listed");
+        assert_eq!(token[1].new_value, json!(true));
+
+        for spelling in ["aa", "0XaA"] {
+            let filter = AuditFilter {
+                token: Some(spelling.to_string()),
+                limit: 10,
+                ..Default::default()
+            };
+
+            assert_eq!(log.query(&filter).unwrap(), token);
+        }
+
+        let range = log
+            .query(&AuditFilter {
+                from: Some(150),
+                to: Some(200),
+                limit: 1,
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(range.len(), 1);
+        assert_eq!(range[0].key_id, "ops");
+        assert_eq!(range[0].token, "0xbb");
+    }
+}
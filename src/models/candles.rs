@@ -0,0 +1,601 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sled::{Batch, Db, Tree};
+use std::{collections::HashMap, io::Error, sync::Mutex};
+use utoipa::ToSchema;
+
+use crate::{
+    config::{
+        candles::{CANDLES_1H_RETENTION_SECS, CANDLES_5M_RETENTION_SECS, CANDLES_KEY},
+        settings::CandlesSettings,
+    },
+    models::{
+        changes::{Changes, CHANGE_WINDOWS_SECS},
+        history::RatesHistory,
+        meta::Token,
+    },
+};
+
+/// Asset name of ZIL itself; tokens are named by lowercase base16.
+pub const ZIL: &str = "zil";
+const ZIL_DECIMALS: i32 = 12;
+
+/// OHLC candles of ZIL and the listed tokens, folded in tick by tick.
+///
+/// One tree per resolution, keyed by `<asset>/<currency>/` followed by the
+/// big-endian start of the candle, so a series is a prefix scan.
+#[derive(Debug)]
+pub struct Candles {
+    db: Db,
+    trees: HashMap<Resolution, Tree>,
+    /// Lowercase codes of the currencies besides ZIL.
+    currencies: Vec<String>,
+    /// Held while folding ticks in, so that the rates and dex updaters
+    /// cannot lose each other's updates to a candle.
+    observing: Mutex<()>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Resolution {
+    /// Kept for 7 days.
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    /// Kept for 90 days.
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+    /// Weeks start on Monday, 00:00 UTC.
+    #[serde(rename = "1w")]
+    Week,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Candle {
+    /// Start of the candle, unix seconds.
+    pub timestamp: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+/// Price of one asset in one currency at the time of a tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tick {
+    pub asset: String,
+    /// Lowercase currency code, or `zil`.
+    pub currency: String,
+    pub price: f64,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 4] = [
+        Resolution::FiveMinutes,
+        Resolution::Hour,
+        Resolution::Day,
+        Resolution::Week,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Resolution::ALL.into_iter().find(|r| r.as_str() == value)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::FiveMinutes => "5m",
+            Resolution::Hour => "1h",
+            Resolution::Day => "1d",
+            Resolution::Week => "1w",
+        }
+    }
+
+    pub fn secs(&self) -> u64 {
+        match self {
+            Resolution::FiveMinutes => 300,
+            Resolution::Hour => 3600,
+            Resolution::Day => 86400,
+            Resolution::Week => 7 * 86400,
+        }
+    }
+
+    /// Start of the candle holding `timestamp`.
+    pub fn bucket(&self, timestamp: u64) -> u64 {
+        match self {
+            // The epoch is a Thursday; Monday is 3 days before it.
+            Resolution::Week => timestamp - (timestamp + 3 * 86400) % self.secs(),
+            _ => timestamp - timestamp % self.secs(),
+        }
+    }
+
+    fn retention_secs(&self) -> Option<u64> {
+        match self {
+            Resolution::FiveMinutes => Some(CANDLES_5M_RETENTION_SECS),
+            Resolution::Hour => Some(CANDLES_1H_RETENTION_SECS),
+            Resolution::Day | Resolution::Week => None,
+        }
+    }
+}
+
+impl Candles {
+    pub fn new(db_path: &str, settings: &CandlesSettings) -> Self {
+        let db = sled::open(format!("{}/{}", db_path, CANDLES_KEY))
+            .expect("Cannot open candles database.");
+        let trees: HashMap<Resolution, Tree> = Resolution::ALL
+            .into_iter()
+            .map(|r| {
+                let tree = db.open_tree(r.as_str()).expect("Cannot open candles tree.");
+
+                (r, tree)
+            })
+            .collect();
+
+        info!("CANDLES: {} hourly candles", trees[&Resolution::Hour].len());
+
+        Candles {
+            db,
+            trees,
+            currencies: settings
+                .currencies
+                .iter()
+                .map(|c| c.to_lowercase())
+                .collect(),
+            observing: Mutex::new(()),
+        }
+    }
+
+    /// Folds the prices of ZIL and the listed tokens at `timestamp` in.
+    pub fn update(
+        &self,
+        timestamp: u64,
+        tokens: &[Token],
+        pools: &HashMap<String, (u128, u128)>,
+        rates: &Map<String, Value>,
+    ) -> Result<(), Error> {
+        self.observe(timestamp, &ticks(tokens, pools, rates, &self.currencies))
+    }
+
+    /// Whether `asset` has candles in `currency`.
+    pub fn quotes(&self, asset: &str, currency: &str) -> bool {
+        let currency = currency.to_lowercase();
+
+        (currency == ZIL && asset != ZIL) || self.currencies.contains(&currency)
+    }
+
+    /// Folds every tick into its candle at each resolution, opening a new
+    /// candle when the tick starts a bucket, and drops candles past their
+    /// retention.
+    pub fn observe(&self, timestamp: u64, ticks: &[Tick]) -> Result<(), Error> {
+        let _observing = self.observing.lock().unwrap_or_else(|e| e.into_inner());
+
+        for (resolution, tree) in &self.trees {
+            let start = resolution.bucket(timestamp);
+            let cutoff = resolution
+                .retention_secs()
+                .map(|r| timestamp.saturating_sub(r));
+            let mut batch = Batch::default();
+
+            for tick in ticks {
+                if !(tick.price.is_finite() && tick.price > 0.0) {
+                    continue;
+                }
+
+                let prefix = prefix(&tick.asset, &tick.currency);
+                let current = key(&prefix, start);
+                let ohlc = match tree.get(&current)? {
+                    Some(value) => {
+                        let (open, high, low, _): (f64, f64, f64, f64) =
+                            serde_json::from_slice(&value)?;
+
+                        (open, high.max(tick.price), low.min(tick.price), tick.price)
+                    }
+                    None => (tick.price, tick.price, tick.price, tick.price),
+                };
+
+                batch.insert(current, serde_json::to_vec(&ohlc)?);
+
+                if let Some(cutoff) = cutoff {
+                    for item in tree.range(key(&prefix, 0)..key(&prefix, cutoff)) {
+                        batch.remove(item?.0);
+                    }
+                }
+            }
+
+            tree.apply_batch(batch)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replays the rates history older than the stored ZIL candles of each
+    /// currency, so that a new store or currency does not start empty, and
+    /// returns the number of points replayed. Only whole weeks before the
+    /// first candle are replayed, as a candle cannot take ticks out of
+    /// order. Tokens have no stored pool history to replay.
+    pub fn backfill(&self, history: &RatesHistory) -> Result<usize, Error> {
+        let mut replayed = 0;
+
+        for currency in &self.currencies {
+            let prefix = prefix(ZIL, currency);
+            let before = match self.trees[&Resolution::Day].scan_prefix(&prefix).next() {
+                Some(item) => Resolution::Week.bucket(start(&item?.0, &prefix)),
+                None => u64::MAX,
+            };
+
+            for point in history.replay(currency, before)? {
+                let tick = Tick {
+                    asset: ZIL.to_string(),
+                    currency: currency.clone(),
+                    price: point.price,
+                };
+
+                self.observe(point.timestamp, &[tick])?;
+                replayed += 1;
+            }
+        }
+
+        Ok(replayed)
+    }
+
+    /// The newest `limit` candles of `asset` in `currency`, oldest first.
+    pub fn series(
+        &self,
+        asset: &str,
+        currency: &str,
+        resolution: Resolution,
+        limit: usize,
+    ) -> Result<Vec<Candle>, Error> {
+        let prefix = prefix(asset, currency);
+        let mut candles = Vec::new();
+
+        for item in self.trees[&resolution]
+            .scan_prefix(&prefix)
+            .rev()
+            .take(limit)
+        {
+            let (key, value) = item?;
+            let (open, high, low, close) = serde_json::from_slice(&value)?;
+            let timestamp = start(&key, &prefix);
+
+            candles.push(Candle {
+                timestamp,
+                open,
+                high,
+                low,
+                close,
+            });
+        }
+
+        candles.reverse();
+
+        Ok(candles)
+    }
+
+    /// Changes of the ZIL price of every listed token with a pool, by
+    /// lowercase base16, from the closes one window of
+    /// `CHANGE_WINDOWS_SECS` ago. A window looks up a candle that ended
+    /// within half its length; tokens without any are left out.
+    pub fn token_changes(
+        &self,
+        now: u64,
+        tokens: &[Token],
+        pools: &HashMap<String, (u128, u128)>,
+    ) -> Result<HashMap<String, Changes>, Error> {
+        let mut changes = HashMap::new();
+
+        for token in tokens.iter().filter(|t| t.listed && t.status == 1) {
+            let Some(price) = pool_price(token, pools) else {
+                continue;
+            };
+            let asset = token.base16.to_lowercase();
+            let mut past = [None; 3];
+
+            for (close, window) in past.iter_mut().zip(CHANGE_WINDOWS_SECS) {
+                *close = self.close_at(&asset, ZIL, now.saturating_sub(window), window / 2)?;
+            }
+
+            let token_changes = Changes::new(price, past);
+
+            if !token_changes.is_empty() {
+                changes.insert(asset, token_changes);
+            }
+        }
+
+        Ok(changes)
+    }
+
+    pub fn flush(&self) -> Result<usize, Error> {
+        Ok(self.db.flush()?)
+    }
+
+    /// Close of the latest candle ended by `timestamp`, from the finest
+    /// resolution holding one that ended at most `max_age` seconds before.
+    fn close_at(
+        &self,
+        asset: &str,
+        currency: &str,
+        timestamp: u64,
+        max_age: u64,
+    ) -> Result<Option<f64>, Error> {
+        let prefix = prefix(asset, currency);
+
+        for resolution in Resolution::ALL {
+            let span = resolution.secs();
+            let Some(latest_start) = (timestamp + 1).checked_sub(span) else {
+                continue;
+            };
+            let Some(item) = self.trees[&resolution]
+                .range(key(&prefix, 0)..=key(&prefix, latest_start))
+                .next_back()
+            else {
+                continue;
+            };
+            let (candle_key, value) = item?;
+
+            if start(&candle_key, &prefix) + span + max_age > timestamp {
+                let (_, _, _, close): (f64, f64, f64, f64) = serde_json::from_slice(&value)?;
+
+                return Ok(Some(close));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Prices of ZIL in `currencies`, and of every listed token with a pool in
+/// ZIL and in `currencies`, from the pool reserves.
+pub fn ticks(
+    tokens: &[Token],
+    pools: &HashMap<String, (u128, u128)>,
+    rates: &Map<String, Value>,
+    currencies: &[String],
+) -> Vec<Tick> {
+    let zil_prices: Vec<(String, f64)> = currencies
+        .iter()
+        .map(|c| c.to_lowercase())
+        .filter_map(|c| {
+            let price = rates.get(&c)?.as_f64()?;
+
+            Some((c, price))
+        })
+        .collect();
+    let mut ticks: Vec<Tick> = zil_prices
+        .iter()
+        .map(|(currency, price)| Tick {
+            asset: ZIL.to_string(),
+            currency: currency.clone(),
+            price: *price,
+        })
+        .collect();
+
+    for token in tokens.iter().filter(|t| t.listed && t.status == 1) {
+        let Some(price) = pool_price(token, pools) else {
+            continue;
+        };
+        let asset = token.base16.to_lowercase();
+
+        ticks.push(Tick {
+            asset: asset.clone(),
+            currency: ZIL.to_string(),
+            price,
+        });
+        ticks.extend(zil_prices.iter().map(|(currency, zil)| Tick {
+            asset: asset.clone(),
+            currency: currency.clone(),
+            price: price * zil,
+        }));
+    }
+
+    ticks
+}
+
+/// ZIL price of `token` from the reserves of its pool.
+pub fn pool_price(token: &Token, pools: &HashMap<String, (u128, u128)>) -> Option<f64> {
+    let &(zils, amount) = pools.get(&token.base16)?;
+    let zils = zils as f64 / 10f64.powi(ZIL_DECIMALS);
+    let amount = amount as f64 / 10f64.powi(token.decimals as i32);
+
+    (amount > 0.0).then(|| zils / amount)
+}
+
+fn prefix(asset: &str, currency: &str) -> Vec<u8> {
+    format!("{}/{}/", asset.to_lowercase(), currency.to_lowercase()).into_bytes()
+}
+
+fn key(prefix: &[u8], timestamp: u64) -> Vec<u8> {
+    [prefix, &timestamp.to_be_bytes()].concat()
+}
+
+/// Start of the candle stored at `key` in the series of `prefix`.
+fn start(key: &[u8], prefix: &[u8]) -> u64 {
+    key[prefix.len()..]
+        .try_into()
+        .map(u64::from_be_bytes)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tick(asset: &str, price: f64) -> Tick {
+        Tick {
+            asset: asset.to_string(),
+            currency: "usd".to_string(),
+            price,
+        }
+    }
+
+    #[test]
+    fn test_bucket() {
+        // 2024-01-03 is a Wednesday; its week started on Monday 2024-01-01.
+        let wednesday = 1_704_283_200;
+
+        assert_eq!(Resolution::Week.bucket(wednesday), 1_704_067_200);
+        assert_eq!(Resolution::FiveMinutes.bucket(1_000), 900);
+        assert_eq!(Resolution::parse("1w"), Some(Resolution::Week));
+        assert_eq!(Resolution::parse("15m"), None);
+    }
+
+    #[test]
+    fn test_observe_folds_ticks() {
+        let dir = tempfile::tempdir().unwrap();
+        let candles = Candles::new(dir.path().to_str().unwrap(), &CandlesSettings::default());
+        let hour = 1_704_067_200;
+
+        for (offset, price) in [(0, 2.0), (60, 3.0), (120, 1.0), (240, 1.5), (300, 1.6)] {
+            candles.observe(hour + offset, &[tick(ZIL, price)]).unwrap();
+        }
+
+        let five = candles
+            .series(ZIL, "USD", Resolution::FiveMinutes, 10)
+            .unwrap();
+        assert_eq!(five.len(), 2);
+        assert_eq!(
+            (five[0].open, five[0].high, five[0].low, five[0].close),
+            (2.0, 3.0, 1.0, 1.5)
+        );
+        assert_eq!(five[1].timestamp, hour + 300);
+
+        assert!(candles.quotes(ZIL, "USD"));
+        assert!(!candles.quotes(ZIL, "zil"));
+        assert!(candles.quotes("0xab", "zil"));
+
+        let hourly = candles.series(ZIL, "usd", Resolution::Hour, 10).unwrap();
+        assert_eq!(
+            hourly,
+            vec![Candle {
+                timestamp: hour,
+                open: 2.0,
+                high: 3.0,
+                low: 1.0,
+                close: 1.6,
+            }]
+        );
+        assert_eq!(
+            candles
+                .series(ZIL, "usd", Resolution::FiveMinutes, 1)
+                .unwrap()[0]
+                .close,
+            1.6
+        );
+
+        // A week later the 5 minute candles have aged out, the hourly stay.
+        candles
+            .observe(hour + 8 * 86400, &[tick(ZIL, 4.0)])
+            .unwrap();
+        assert_eq!(
+            candles
+                .series(ZIL, "usd", Resolution::FiveMinutes, 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            candles
+                .series(ZIL, "usd", Resolution::Hour, 10)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_backfill_from_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        let candles = Candles::new(path, &CandlesSettings::default());
+        let history = RatesHistory::new(path);
+        let monday = 1_704_067_200;
+        let prices = |usd: f64| json!({ "usd": usd }).as_object().cloned().unwrap();
+
+        history.append(monday + 60, &prices(1.0)).unwrap();
+        history.append(monday + 120, &prices(3.0)).unwrap();
+        history.append(monday + 180, &prices(2.0)).unwrap();
+
+        assert_eq!(candles.backfill(&history).unwrap(), 3);
+        let daily = candles.series(ZIL, "usd", Resolution::Day, 10).unwrap();
+        assert_eq!(
+            daily,
+            vec![Candle {
+                timestamp: monday,
+                open: 1.0,
+                high: 3.0,
+                low: 1.0,
+                close: 2.0,
+            }]
+        );
+
+        // Nothing is older than the stored candles any more.
+        assert_eq!(candles.backfill(&history).unwrap(), 0);
+    }
+
+    fn token() -> Token {
+        Token {
+            bech32: String::new(),
+            base16: "0xAB".to_string(),
+            scope: 0,
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            token_type: 1,
+            decimals: 6,
+            listed: true,
+            status: 1,
+        }
+    }
+
+    #[test]
+    fn test_token_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let candles = Candles::new(dir.path().to_str().unwrap(), &CandlesSettings::default());
+        let now = 1_704_067_200 + 10 * 86400;
+        let pools = HashMap::from([("0xAB".to_string(), (4_000_000_000_000, 2_000_000))]);
+        let zil_tick = |price| Tick {
+            asset: "0xab".to_string(),
+            currency: ZIL.to_string(),
+            price,
+        };
+
+        candles.observe(now - 4000, &[zil_tick(1.6)]).unwrap();
+        // Still open an hour ago, so it does not count.
+        candles.observe(now - 3590, &[zil_tick(1.0)]).unwrap();
+
+        let changes = candles.token_changes(now, &[token()], &pools).unwrap();
+        let hour = changes["0xab"].hour.unwrap();
+
+        assert!((hour.percent - 25.0).abs() < 1e-9);
+        assert!(changes["0xab"].day.is_none());
+        assert!(candles
+            .token_changes(now - 7200, &[token()], &pools)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_ticks_from_pools() {
+        let pools = HashMap::from([("0xAB".to_string(), (4_000_000_000_000, 2_000_000))]);
+        let rates = json!({ "usd": 0.02, "eur": 0.018 });
+        let ticks = ticks(
+            &[token()],
+            &pools,
+            rates.as_object().unwrap(),
+            &["USD".to_string()],
+        );
+
+        assert_eq!(
+            ticks,
+            vec![
+                tick(ZIL, 0.02),
+                Tick {
+                    asset: "0xab".to_string(),
+                    currency: ZIL.to_string(),
+                    price: 2.0,
+                },
+                tick("0xab", 0.04),
+            ]
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Lengths of the windows of `Changes`, in seconds.
+pub const CHANGE_WINDOWS_SECS: [u64; 3] = [3600, 24 * 3600, 7 * 24 * 3600];
+
+/// Move of a price over the last hour, day and week. A window is absent
+/// when no price that old is stored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Changes {
+    #[serde(rename = "1h", default, skip_serializing_if = "Option::is_none")]
+    pub hour: Option<Change>,
+    #[serde(rename = "24h", default, skip_serializing_if = "Option::is_none")]
+    pub day: Option<Change>,
+    #[serde(rename = "7d", default, skip_serializing_if = "Option::is_none")]
+    pub week: Option<Change>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Change {
+    /// Current price minus the past one.
+    pub absolute: f64,
+    /// `absolute` relative to the past price, e.g. `3.2` for +3.2%.
+    pub percent: f64,
+}
+
+impl Changes {
+    /// From the prices one window of `CHANGE_WINDOWS_SECS` ago each.
+    pub fn new(current: f64, past: [Option<f64>; 3]) -> Self {
+        let [hour, day, week] = past.map(|p| p.and_then(|p| Change::between(p, current)));
+
+        Changes { hour, day, week }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hour.is_none() && self.day.is_none() && self.week.is_none()
+    }
+}
+
+impl Change {
+    pub fn between(past: f64, current: f64) -> Option<Self> {
+        if !(past.is_finite() && current.is_finite() && past > 0.0) {
+            return None;
+        }
+
+        let absolute = current - past;
+
+        Some(Change {
+            absolute,
+            percent: absolute / past * 100.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_changes() {
+        let changes = Changes::new(1.5, [Some(1.0), None, Some(0.0)]);
+
+        assert_eq!(
+            changes.hour,
+            Some(Change {
+                absolute: 0.5,
+                percent: 50.0
+            })
+        );
+        assert!(changes.week.is_none());
+        assert_eq!(
+            serde_json::to_value(&changes).unwrap(),
+            json!({ "1h": { "absolute": 0.5, "percent": 50.0 } })
+        );
+        assert!(Changes::new(1.0, [None; 3]).is_empty());
+    }
+}
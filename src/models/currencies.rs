@@ -1,22 +1,211 @@
-use std::io::{Error, ErrorKind};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Error,
+    ops::{Deref, DerefMut},
+    time::Instant,
+};
 
-use crate::config::currencies::{CURRENCIES, CURRENCIES_KEY};
-use log::{error, info};
-use reqwest::Client;
-use serde::Deserialize;
-use serde_json::{Map, Value};
+use crate::{
+    components::rates::{Quoting, WeightedProvider},
+    config::{
+        currencies::{CURRENCIES, CURRENCIES_KEY},
+        settings::{RateSource, RatesSettings},
+    },
+    metrics,
+    models::changes::Changes,
+};
+use futures_util::future::join_all;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use sled::{Db, IVec};
+use utoipa::ToSchema;
 
 #[derive(Debug)]
 pub struct Currencies {
-    pub data: Map<String, Value>,
+    pub data: Rates,
     db: Db,
     app_name: &'static str,
 }
 
-#[derive(Deserialize, Debug)]
-struct APIResponse {
-    zilliqa: Map<String, Value>,
+/// Aggregated prices with the metadata of their last update. Stored as
+/// is, and served so by `/api/v1/rates?details=1`; `/api/v1/rates` serves
+/// the prices alone.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rates {
+    #[serde(flatten)]
+    pub prices: Prices,
+    /// Sources that contributed to at least one price, by name.
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceReport>,
+    /// Moves of each price, by lowercase currency code.
+    #[serde(default)]
+    pub changes: BTreeMap<String, Changes>,
+    /// Currencies no source quotes, with when their last price started to
+    /// be carried over.
+    #[serde(default)]
+    pub stale: BTreeMap<String, u64>,
+}
+
+/// ZIL price per lowercase currency code, as served by `/api/v1/rates`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(transparent)]
+#[schema(value_type = HashMap<String, f64>)]
+pub struct Prices(pub Map<String, Value>);
+
+/// Where the prices come from and how they moved, as served by
+/// `/api/v1/rates/details`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RatesDetails {
+    /// Sources that contributed to at least one price, by name.
+    pub sources: BTreeMap<String, SourceReport>,
+    /// 1h, 24h and 7d moves of each price, by lowercase currency code.
+    pub changes: BTreeMap<String, Changes>,
+    /// Currencies whose last price is served because no source quotes
+    /// them now, with the unix seconds since when.
+    pub stale: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SourceReport {
+    pub weight: f64,
+    /// Prices the source contributed to.
+    pub currencies: usize,
+}
+
+/// Prices from one source, in ZIL per lowercase currency code.
+#[derive(Debug, Clone)]
+pub struct Quotes {
+    pub source: RateSource,
+    pub weight: f64,
+    pub prices: HashMap<String, f64>,
+}
+
+impl Deref for Prices {
+    type Target = Map<String, Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Prices {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Rates {
+    /// Weighted median per currency over the sources quoting it; a
+    /// currency no source quotes is left out.
+    pub fn aggregate(quotes: &[Quotes], currencies: &[String]) -> Self {
+        let mut rates = Rates::default();
+
+        for currency in currencies {
+            let code = currency.to_lowercase();
+            let quoting: Vec<(&Quotes, f64)> = quotes
+                .iter()
+                .filter_map(|q| {
+                    let price = *q.prices.get(&code)?;
+
+                    (price.is_finite() && price > 0.0).then_some((q, price))
+                })
+                .collect();
+            let mut values: Vec<(f64, f64)> = quoting.iter().map(|(q, p)| (*p, q.weight)).collect();
+            let Some(median) = weighted_median(&mut values) else {
+                continue;
+            };
+
+            rates.prices.insert(code, json!(median));
+
+            for (quotes, _) in quoting {
+                rates
+                    .sources
+                    .entry(quotes.source.to_string())
+                    .or_insert(SourceReport {
+                        weight: quotes.weight,
+                        currencies: 0,
+                    })
+                    .currencies += 1;
+            }
+        }
+
+        rates
+    }
+
+    /// Keeps the last known price of the `currencies` missing here, marked
+    /// stale since the first update that missed it, and returns how many
+    /// were kept. A price stale for more than `max_age` seconds is dropped.
+    pub fn carry_over(
+        &mut self,
+        previous: &Rates,
+        currencies: &[String],
+        now: u64,
+        max_age: u64,
+    ) -> usize {
+        let mut kept = 0;
+
+        for currency in currencies {
+            let code = currency.to_lowercase();
+
+            if self.prices.contains_key(&code) {
+                continue;
+            }
+
+            let Some(price) = previous.prices.get(&code) else {
+                continue;
+            };
+            let since = previous.stale.get(&code).copied().unwrap_or(now);
+
+            if now.saturating_sub(since) <= max_age {
+                self.prices.insert(code.clone(), price.clone());
+                self.stale.insert(code, since);
+                kept += 1;
+            }
+        }
+
+        kept
+    }
+
+    pub fn details(&self) -> RatesDetails {
+        RatesDetails {
+            sources: self.sources.clone(),
+            changes: self.changes.clone(),
+            stale: self.stale.clone(),
+        }
+    }
+}
+
+/// The value where the cumulative weight reaches half of the total; the
+/// midpoint when it falls exactly between two values.
+pub fn weighted_median(values: &mut [(f64, f64)]) -> Option<f64> {
+    let total: f64 = values.iter().map(|(_, weight)| weight).sum();
+
+    if values.is_empty() || total <= 0.0 {
+        return None;
+    }
+
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let half = total / 2.0;
+    let mut cumulative = 0.0;
+
+    for (i, &(value, weight)) in values.iter().enumerate() {
+        cumulative += weight;
+
+        if (cumulative - half).abs() <= f64::EPSILON * total {
+            return Some(
+                values
+                    .get(i + 1)
+                    .map_or(value, |next| (value + next.0) / 2.0),
+            );
+        }
+        if cumulative > half {
+            return Some(value);
+        }
+    }
+
+    values.last().map(|(value, _)| *value)
 }
 
 impl Currencies {
@@ -29,13 +218,14 @@ impl Currencies {
                 let cache = mb_cache.unwrap_or(IVec::default());
                 let mb_json = std::str::from_utf8(&cache).unwrap_or("{}");
 
-                serde_json::from_str(mb_json).unwrap_or(Map::new())
+                serde_json::from_str(mb_json).unwrap_or_default()
             }
             Err(_) => {
-                let mut data = Map::new();
+                let mut data = Rates::default();
 
                 for currency in CURRENCIES {
-                    data.insert(currency.to_lowercase().to_owned(), Value::from(0.0));
+                    data.prices
+                        .insert(currency.to_lowercase().to_owned(), Value::from(0.0));
                 }
 
                 data
@@ -45,11 +235,15 @@ impl Currencies {
         Currencies { data, db, app_name }
     }
 
+    pub fn flush(&self) -> Result<usize, Error> {
+        Ok(self.db.flush()?)
+    }
+
     pub fn serializatio(&self) -> String {
-        serde_json::to_string(&self.data).unwrap()
+        serde_json::to_string(&self.data).unwrap_or_default()
     }
 
-    pub fn update(&mut self, rates: Map<String, Value>) -> Result<(), Error> {
+    pub fn update(&mut self, rates: Rates) -> Result<(), Error> {
         self.data = rates;
         self.db
             .insert(CURRENCIES_KEY, self.serializatio().as_bytes())?;
@@ -59,32 +253,226 @@ impl Currencies {
         Ok(())
     }
 
-    pub async fn fetch_rates() -> Result<Map<String, Value>, Error> {
-        let data = match Currencies::coingecko().await {
-            Ok(data) => data,
-            Err(e) => {
-                let custom_error = Error::new(ErrorKind::Other, "coingecko is down");
+    /// Aggregates every provider that answers; fails only when none does.
+    pub async fn fetch_rates(
+        providers: &[WeightedProvider],
+        settings: &RatesSettings,
+    ) -> Result<Rates, Error> {
+        let base = settings.base_currency.to_lowercase();
+        let fetched = join_all(providers.iter().map(|p| async move {
+            let started = Instant::now();
+            let result = p.provider.fetch(&settings.currencies).await;
+
+            metrics::observe_upstream(
+                &p.provider.source().to_string(),
+                result.is_ok(),
+                started.elapsed(),
+            );
+
+            (p, result)
+        }))
+        .await;
+        let mut quotes = Vec::new();
+        let mut cross = Vec::new();
 
-                error!("coingecko: cannot load rates, error: {:?}", e);
+        for (p, result) in fetched {
+            let source = p.provider.source();
 
-                return Err(custom_error);
+            match (result, p.provider.quoting()) {
+                (Ok(prices), Quoting::Zil) => quotes.push(Quotes {
+                    source,
+                    weight: p.weight,
+                    prices,
+                }),
+                (Ok(prices), Quoting::Cross) => cross.push((source, p.weight, prices)),
+                (Err(e), _) => warn!("{}: cannot load rates, error: {}", source, e),
             }
+        }
+
+        // Cross-rates have no ZIL price: they are anchored on the ZIL price
+        // in the base currency from the other sources.
+        let mut anchors: Vec<(f64, f64)> = quotes
+            .iter()
+            .filter_map(|q| Some((*q.prices.get(&base)?, q.weight)))
+            .collect();
+        let anchor = weighted_median(&mut anchors);
+
+        for (source, weight, prices) in cross {
+            let Some(anchor) = anchor else {
+                warn!("{}: no {} price to anchor on", source, base);
+                continue;
+            };
+
+            quotes.push(Quotes {
+                source,
+                weight,
+                prices: prices
+                    .into_iter()
+                    .filter(|(code, price)| *code != base && *price > 0.0)
+                    .map(|(code, price)| (code, anchor / price))
+                    .collect(),
+            });
+        }
+
+        let rates = Rates::aggregate(&quotes, &settings.currencies);
+
+        if rates.prices.is_empty() {
+            error!("rates: every source is down");
+
+            return Err(Error::other("every rate source is down"));
+        }
+
+        Ok(rates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::rates::{RatesApiError, RatesFuture, RatesProvider};
+
+    struct Fixed(RateSource, Quoting, Option<&'static [(&'static str, f64)]>);
+
+    impl RatesProvider for Fixed {
+        fn source(&self) -> RateSource {
+            self.0
+        }
+
+        fn quoting(&self) -> Quoting {
+            self.1
+        }
+
+        fn fetch<'a>(&'a self, _: &'a [String]) -> RatesFuture<'a> {
+            Box::pin(async move {
+                let prices = self
+                    .2
+                    .ok_or_else(|| RatesApiError::ApiError("down".to_string()))?;
+
+                Ok(prices.iter().map(|(c, p)| (c.to_string(), *p)).collect())
+            })
+        }
+    }
+
+    fn provider(fixed: Fixed) -> WeightedProvider {
+        WeightedProvider {
+            provider: Box::new(fixed),
+            weight: 1.0,
+        }
+    }
+
+    fn quotes(source: RateSource, weight: f64, prices: &[(&str, f64)]) -> Quotes {
+        Quotes {
+            source,
+            weight,
+            prices: prices.iter().map(|(c, p)| (c.to_string(), *p)).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rates_anchors_cross_rates() {
+        let settings = RatesSettings {
+            base_currency: "AED".to_string(),
+            currencies: ["AED", "USD", "ZAR"].map(String::from).to_vec(),
+            ..Default::default()
         };
+        let providers = [
+            provider(Fixed(
+                RateSource::Coingecko,
+                Quoting::Zil,
+                Some(&[("aed", 0.08), ("usd", 0.02)]),
+            )),
+            provider(Fixed(RateSource::Cryptocompare, Quoting::Zil, None)),
+            provider(Fixed(
+                RateSource::Metals,
+                Quoting::Cross,
+                Some(&[("aed", 1.0), ("zar", 0.25)]),
+            )),
+        ];
+        let rates = Currencies::fetch_rates(&providers, &settings)
+            .await
+            .unwrap();
 
-        Ok(data)
+        assert_eq!(rates.prices["aed"], json!(0.08));
+        assert_eq!(rates.prices["zar"], json!(0.32));
+        assert_eq!(rates.sources["metals"].currencies, 1);
+        assert!(!rates.sources.contains_key("cryptocompare"));
+
+        let down = [provider(Fixed(RateSource::Coingecko, Quoting::Zil, None))];
+        assert!(Currencies::fetch_rates(&down, &settings).await.is_err());
     }
 
-    async fn coingecko() -> Result<Map<String, Value>, reqwest::Error> {
-        let client = Client::new();
-        let url = format!(
-            "https://api.coingecko.com/api/v3/simple/price?ids=zilliqa&vs_currencies={}",
-            CURRENCIES.join(",")
-        )
-        .to_lowercase();
-        let response = client.get(url).send().await?;
+    #[test]
+    fn test_weighted_median() {
+        assert_eq!(weighted_median(&mut []), None);
+        assert_eq!(weighted_median(&mut [(3.0, 1.0)]), Some(3.0));
+        assert_eq!(weighted_median(&mut [(3.0, 1.0), (1.0, 1.0)]), Some(2.0));
+        assert_eq!(
+            weighted_median(&mut [(1.0, 1.0), (2.0, 1.0), (10.0, 1.0)]),
+            Some(2.0)
+        );
+        assert_eq!(
+            weighted_median(&mut [(1.0, 1.0), (2.0, 1.0), (10.0, 3.0)]),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn test_aggregate_with_partial_sources() {
+        let currencies = ["USD", "EUR", "XAU"].map(String::from);
+        let rates = Rates::aggregate(
+            &[
+                quotes(
+                    RateSource::Coingecko,
+                    2.0,
+                    &[("usd", 0.020), ("eur", 0.018)],
+                ),
+                quotes(RateSource::Cryptocompare, 1.0, &[("usd", 0.021)]),
+                quotes(RateSource::Metals, 1.0, &[("eur", f64::NAN)]),
+            ],
+            &currencies,
+        );
+
+        assert_eq!(rates.prices["usd"], json!(0.020));
+        assert_eq!(rates.prices["eur"], json!(0.018));
+        assert!(!rates.prices.contains_key("xau"));
+        assert_eq!(rates.sources["coingecko"].currencies, 2);
+        assert_eq!(rates.sources["cryptocompare"].currencies, 1);
+        assert!(!rates.sources.contains_key("metals"));
+
+        let mut next = Rates::aggregate(
+            &[quotes(RateSource::Cryptocompare, 1.0, &[("usd", 0.022)])],
+            &currencies,
+        );
+        assert_eq!(next.carry_over(&rates, &currencies, 100, 60), 1);
+        assert_eq!(next.prices["eur"], json!(0.018));
+        assert_eq!(next.stale, BTreeMap::from([("eur".to_string(), 100)]));
+
+        // Carried over from the first miss on, until too old.
+        let mut later = Rates::default();
+        assert_eq!(later.carry_over(&next, &currencies, 160, 60), 2);
+        assert_eq!(later.stale["eur"], 100);
+        assert_eq!(later.stale["usd"], 160);
+        let mut expired = Rates::default();
+        assert_eq!(expired.carry_over(&later, &currencies, 161, 60), 1);
+        assert!(!expired.prices.contains_key("eur"));
+        assert_eq!(expired.prices["usd"], json!(0.022));
 
-        let body: APIResponse = response.json().await?;
+        // Stored flat, so snapshots from before sources were kept still load.
+        let stored = serde_json::to_value(&next).unwrap();
+        assert_eq!(stored["usd"], json!(0.022));
+        assert_eq!(serde_json::from_value::<Rates>(stored).unwrap(), next);
+        assert_eq!(
+            serde_json::from_value::<Rates>(json!({ "usd": 0.022 }))
+                .unwrap()
+                .prices["usd"],
+            json!(0.022)
+        );
 
-        Ok(body.zilliqa)
+        assert_eq!(
+            serde_json::to_value(&next.prices).unwrap(),
+            json!({ "usd": 0.022, "eur": 0.018 })
+        );
+        let details = serde_json::to_value(next.details()).unwrap();
+        assert_eq!(details["sources"]["cryptocompare"]["weight"], json!(1.0));
     }
 }
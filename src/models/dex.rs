@@ -3,14 +3,10 @@ use serde::Deserialize;
 use serde_json::json;
 use sled::{Db, IVec};
 use std::collections::HashMap;
-use std::io::{Error, ErrorKind};
-use std::u128;
+use std::io::Error;
 
 use crate::{
-    config::{
-        dex::{DEX, DEX_KEY},
-        zilliqa::RPC_METHODS,
-    },
+    config::{dex::DEX_KEY, zilliqa::RPC_METHODS},
     utils::zilliqa::{JsonBodyReq, JsonBodyRes, Zilliqa},
 };
 
@@ -68,24 +64,34 @@ impl Dex {
         Ok(())
     }
 
-    pub async fn get_pools(zilliqa: &Zilliqa) -> Result<HashMap<String, (u128, u128)>, Error> {
-        let pools = Dex::fetch(zilliqa).await?;
+    pub async fn get_pools(
+        zilliqa: &Zilliqa,
+        contract: &str,
+    ) -> Result<HashMap<String, (u128, u128)>, Error> {
+        let pools = Dex::fetch(zilliqa, contract).await?;
 
         Ok(pools)
     }
 
+    pub fn flush(&self) -> Result<usize, Error> {
+        Ok(self.db.flush()?)
+    }
+
     pub fn serializatio(&self) -> String {
-        serde_json::to_string(&self.pools).unwrap()
+        serde_json::to_string(&self.pools).unwrap_or_default()
     }
 
-    async fn fetch(zilliqa: &Zilliqa) -> Result<HashMap<String, (u128, u128)>, Error> {
+    async fn fetch(
+        zilliqa: &Zilliqa,
+        contract: &str,
+    ) -> Result<HashMap<String, (u128, u128)>, Error> {
         let field = "pools";
-        let custom_error = Error::new(ErrorKind::Other, "Fail to fetch or parse response");
-        let params = json!([DEX, field, []]);
+        let custom_error = Error::other("Fail to fetch or parse response");
+        let params = json!([contract, field, []]);
         let bodies: Vec<JsonBodyReq> =
             vec![zilliqa.build_body(RPC_METHODS.get_smart_contract_sub_state, params)];
         let res: Vec<JsonBodyRes<ResPoolState>> = zilliqa.fetch(bodies).await?;
-        let pools = match res.get(0) {
+        let pools = match res.first() {
             Some(res) => match &res.result {
                 Some(result) => &result.pools,
                 None => return Err(custom_error),
@@ -95,11 +101,11 @@ impl Dex {
             }
         };
         let pools: HashMap<String, (u128, u128)> = pools
-            .into_iter()
+            .iter()
             .filter_map(|(key, value)| {
                 let key = key.to_string();
-                let zils: u128 = value.arguments.0.parse().unwrap();
-                let tokens: u128 = value.arguments.1.parse().unwrap();
+                let zils: u128 = value.arguments.0.parse().ok()?;
+                let tokens: u128 = value.arguments.1.parse().ok()?;
                 let args = (zils, tokens);
 
                 if zils == 0 || tokens == 0 {
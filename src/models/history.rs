@@ -0,0 +1,400 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sled::{Batch, Db, Tree};
+use std::{collections::BTreeMap, io::Error};
+use utoipa::ToSchema;
+
+use crate::{
+    config::history::{
+        HISTORY_DAILY_TREE, HISTORY_HOURLY_RETENTION_SECS, HISTORY_HOURLY_TREE, HISTORY_KEY,
+        HISTORY_RAW_RETENTION_SECS, HISTORY_RAW_TREE,
+    },
+    models::changes::{Changes, CHANGE_WINDOWS_SECS},
+};
+
+/// Accepted rate snapshots, downsampled as they age.
+///
+/// Each tier is a tree keyed by the big-endian timestamp of its points, so
+/// time ranges are plain range scans. An hourly or daily point is stamped
+/// with the start of its bucket and holds the latest snapshot within it.
+#[derive(Debug)]
+pub struct RatesHistory {
+    db: Db,
+    raw: Tree,
+    hourly: Tree,
+    daily: Tree,
+}
+
+/// Resolution of a series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Interval {
+    /// Every snapshot, for the last 24 hours.
+    #[serde(rename = "raw")]
+    Raw,
+    /// One point per hour, for the last 90 days.
+    #[serde(rename = "1h")]
+    Hour,
+    /// One point per day, kept forever.
+    #[serde(rename = "1d")]
+    Day,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct HistoryPoint {
+    /// Unix seconds.
+    pub timestamp: u64,
+    /// ZIL price in the currency.
+    pub price: f64,
+}
+
+impl Interval {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "raw" => Some(Interval::Raw),
+            "1h" => Some(Interval::Hour),
+            "1d" => Some(Interval::Day),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::Raw => "raw",
+            Interval::Hour => "1h",
+            Interval::Day => "1d",
+        }
+    }
+
+    /// Span of time one point covers.
+    fn bucket_secs(&self) -> u64 {
+        match self {
+            Interval::Raw => 1,
+            Interval::Hour => 3600,
+            Interval::Day => 86400,
+        }
+    }
+
+    fn retention_secs(&self) -> Option<u64> {
+        match self {
+            Interval::Raw => Some(HISTORY_RAW_RETENTION_SECS),
+            Interval::Hour => Some(HISTORY_HOURLY_RETENTION_SECS),
+            Interval::Day => None,
+        }
+    }
+
+    /// The finest interval still holding points as old as `from`.
+    pub fn covering(from: u64, now: u64) -> Self {
+        let age = now.saturating_sub(from);
+
+        [Interval::Raw, Interval::Hour]
+            .into_iter()
+            .find(|i| i.retention_secs().is_some_and(|r| age <= r))
+            .unwrap_or(Interval::Day)
+    }
+}
+
+impl RatesHistory {
+    pub fn new(db_path: &str) -> Self {
+        let db = sled::open(format!("{}/{}", db_path, HISTORY_KEY))
+            .expect("Cannot open history database.");
+        let open = |name: &str| db.open_tree(name).expect("Cannot open history tree.");
+        let (raw, hourly, daily) = (
+            open(HISTORY_RAW_TREE),
+            open(HISTORY_HOURLY_TREE),
+            open(HISTORY_DAILY_TREE),
+        );
+
+        info!(
+            "HISTORY: {} raw, {} hourly, {} daily points",
+            raw.len(),
+            hourly.len(),
+            daily.len()
+        );
+
+        RatesHistory {
+            db,
+            raw,
+            hourly,
+            daily,
+        }
+    }
+
+    /// Stores the snapshot taken at `timestamp` in every tier and drops the
+    /// points that aged out of theirs.
+    pub fn append(&self, timestamp: u64, prices: &Map<String, Value>) -> Result<(), Error> {
+        let value = serde_json::to_vec(prices)?;
+
+        self.raw.insert(timestamp.to_be_bytes(), value.as_slice())?;
+        for interval in [Interval::Hour, Interval::Day] {
+            let start = bucket(timestamp, interval.bucket_secs());
+
+            self.tree(interval)
+                .insert(start.to_be_bytes(), value.as_slice())?;
+        }
+        for interval in [Interval::Raw, Interval::Hour] {
+            if let Some(retention) = interval.retention_secs() {
+                self.prune(self.tree(interval), timestamp.saturating_sub(retention))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Prices of `currency` between `from` and `to` inclusive, oldest first.
+    pub fn series(
+        &self,
+        currency: &str,
+        from: u64,
+        to: u64,
+        interval: Interval,
+    ) -> Result<Vec<HistoryPoint>, Error> {
+        let currency = currency.to_lowercase();
+        let mut points = Vec::new();
+
+        for item in self
+            .tree(interval)
+            .range(from.to_be_bytes()..=to.to_be_bytes())
+        {
+            let (key, value) = item?;
+            let prices: Map<String, Value> = serde_json::from_slice(&value)?;
+
+            if let Some(price) = prices.get(&currency).and_then(Value::as_f64) {
+                points.push(HistoryPoint {
+                    timestamp: timestamp(&key),
+                    price,
+                });
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// Prices of `currency` before `before`, oldest first, each span from
+    /// the finest tier still holding it. A downsampled point is only taken
+    /// when its bucket ended before the finer tier starts.
+    pub fn replay(&self, currency: &str, before: u64) -> Result<Vec<HistoryPoint>, Error> {
+        let mut points = Vec::new();
+        let mut until = before;
+
+        for interval in [Interval::Raw, Interval::Hour, Interval::Day] {
+            let Some(to) = until.checked_sub(interval.bucket_secs()) else {
+                continue;
+            };
+            let mut older = self.series(currency, 0, to, interval)?;
+
+            older.append(&mut points);
+            points = older;
+
+            if let Some((key, _)) = self.tree(interval).first()? {
+                until = until.min(timestamp(&key));
+            }
+        }
+
+        Ok(points)
+    }
+
+    /// The latest snapshot at or before `timestamp`, from the finest tier
+    /// holding one at most `max_age` seconds older. A downsampled point
+    /// only qualifies once its bucket has ended, as it holds the last
+    /// snapshot within.
+    pub fn at(&self, timestamp: u64, max_age: u64) -> Result<Option<Map<String, Value>>, Error> {
+        for interval in [Interval::Raw, Interval::Hour, Interval::Day] {
+            let span = interval.bucket_secs();
+            let Some(latest_start) = (timestamp + 1).checked_sub(span) else {
+                continue;
+            };
+            let Some(item) = self
+                .tree(interval)
+                .range(..=latest_start.to_be_bytes())
+                .next_back()
+            else {
+                continue;
+            };
+            let (key, value) = item?;
+
+            if self::timestamp(&key) + span + max_age > timestamp {
+                return Ok(Some(serde_json::from_slice(&value)?));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Changes of each of `prices` at `now` since the snapshots one window
+    /// of `CHANGE_WINDOWS_SECS` ago. A window looks up a snapshot within
+    /// half its length; currencies without any are left out.
+    pub fn changes(
+        &self,
+        now: u64,
+        prices: &Map<String, Value>,
+    ) -> Result<BTreeMap<String, Changes>, Error> {
+        let mut past = Vec::with_capacity(CHANGE_WINDOWS_SECS.len());
+
+        for window in CHANGE_WINDOWS_SECS {
+            past.push(self.at(now.saturating_sub(window), window / 2)?);
+        }
+
+        Ok(prices
+            .iter()
+            .filter_map(|(code, price)| {
+                let price_at = |i: usize| past[i].as_ref()?.get(code)?.as_f64();
+                let changes =
+                    Changes::new(price.as_f64()?, [price_at(0), price_at(1), price_at(2)]);
+
+                (!changes.is_empty()).then(|| (code.clone(), changes))
+            })
+            .collect())
+    }
+
+    pub fn flush(&self) -> Result<usize, Error> {
+        Ok(self.db.flush()?)
+    }
+
+    fn tree(&self, interval: Interval) -> &Tree {
+        match interval {
+            Interval::Raw => &self.raw,
+            Interval::Hour => &self.hourly,
+            Interval::Day => &self.daily,
+        }
+    }
+
+    fn prune(&self, tree: &Tree, before: u64) -> Result<(), Error> {
+        let mut batch = Batch::default();
+
+        for item in tree.range(..before.to_be_bytes()) {
+            batch.remove(item?.0);
+        }
+
+        Ok(tree.apply_batch(batch)?)
+    }
+}
+
+fn bucket(timestamp: u64, secs: u64) -> u64 {
+    timestamp - timestamp % secs
+}
+
+fn timestamp(key: &[u8]) -> u64 {
+    key.try_into().map(u64::from_be_bytes).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn prices(usd: f64) -> Map<String, Value> {
+        json!({ "usd": usd, "eur": usd * 0.9 })
+            .as_object()
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_interval_covering() {
+        let now = 100 * 86400;
+
+        assert_eq!(Interval::covering(now - 3600, now), Interval::Raw);
+        assert_eq!(Interval::covering(now - 2 * 86400, now), Interval::Hour);
+        assert_eq!(Interval::covering(0, now), Interval::Day);
+        assert_eq!(Interval::parse("1h"), Some(Interval::Hour));
+        assert_eq!(Interval::parse("5m"), None);
+    }
+
+    #[test]
+    fn test_append_downsamples_and_prunes() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = RatesHistory::new(dir.path().to_str().unwrap());
+        let day = 86400;
+
+        history.append(day + 10, &prices(1.0)).unwrap();
+        history.append(day + 3000, &prices(2.0)).unwrap();
+        history.append(day + 3700, &prices(3.0)).unwrap();
+
+        let raw = history.series("USD", 0, u64::MAX, Interval::Raw).unwrap();
+        assert_eq!(
+            raw.iter().map(|p| p.price).collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.0]
+        );
+
+        let hourly = history.series("usd", 0, u64::MAX, Interval::Hour).unwrap();
+        assert_eq!(
+            hourly,
+            vec![
+                HistoryPoint {
+                    timestamp: day,
+                    price: 2.0
+                },
+                HistoryPoint {
+                    timestamp: day + 3600,
+                    price: 3.0
+                },
+            ]
+        );
+
+        // Raw snapshots older than a day are gone, hourly points older
+        // than 90 days too; daily points stay.
+        history.append(2 * day + 3000, &prices(4.0)).unwrap();
+        let raw = history.series("usd", 0, u64::MAX, Interval::Raw).unwrap();
+        assert_eq!(raw.first().map(|p| p.timestamp), Some(day + 3000));
+
+        history.append(92 * day, &prices(5.0)).unwrap();
+        let hourly = history.series("usd", 0, u64::MAX, Interval::Hour).unwrap();
+        assert_eq!(hourly.first().map(|p| p.timestamp), Some(2 * day));
+
+        let daily = history.series("usd", 0, u64::MAX, Interval::Day).unwrap();
+        assert_eq!(
+            daily
+                .iter()
+                .map(|p| (p.timestamp, p.price))
+                .collect::<Vec<_>>(),
+            vec![(day, 3.0), (2 * day, 4.0), (92 * day, 5.0)]
+        );
+        assert!(history
+            .series("xau", 0, u64::MAX, Interval::Day)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_replay_prefers_finer_tiers() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = RatesHistory::new(dir.path().to_str().unwrap());
+        let day = 86400;
+
+        history.append(day + 10, &prices(1.0)).unwrap();
+        history.append(3 * day + 10, &prices(2.0)).unwrap();
+        history.append(3 * day + 20, &prices(3.0)).unwrap();
+
+        // The first snapshot only survives downsampled.
+        let points = history.replay("usd", u64::MAX).unwrap();
+        assert_eq!(
+            points
+                .iter()
+                .map(|p| (p.timestamp, p.price))
+                .collect::<Vec<_>>(),
+            vec![(day, 1.0), (3 * day + 10, 2.0), (3 * day + 20, 3.0)]
+        );
+        assert_eq!(history.replay("usd", 3 * day + 20).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_changes_from_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let history = RatesHistory::new(dir.path().to_str().unwrap());
+        let now = 30 * 86400;
+
+        history.append(now - 7 * 86400 - 600, &prices(1.0)).unwrap();
+        history.append(now - 3600 - 20, &prices(2.0)).unwrap();
+        history.append(now - 20, &prices(2.2)).unwrap();
+
+        let changes = history.changes(now, &prices(2.5)).unwrap();
+        let usd = &changes["usd"];
+
+        assert!((usd.hour.unwrap().percent - 25.0).abs() < 1e-9);
+        assert!((usd.week.unwrap().absolute - 1.5).abs() < 1e-9);
+        // Nothing was stored within 12 hours of a day ago.
+        assert!(usd.day.is_none());
+        assert!(changes.contains_key("eur"));
+    }
+}
@@ -1,8 +1,7 @@
-use std::u8;
-
 use crate::{
     config::{
-        meta::{CRYPTO_META_URL, META_KEY, MIN_SCORE, TOKENS_EXCEPTIONS},
+        meta::{META_KEY, META_PINS_TREE},
+        settings::MetaSettings,
         zilliqa::RPC_METHODS,
     },
     utils::{
@@ -14,24 +13,32 @@ use log::{error, info};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
-use sled::Db;
+use sled::{Db, Tree};
 use std::{
-    collections::HashSet,
-    io::{Error, ErrorKind},
+    collections::{HashMap, HashSet},
+    io::Error,
 };
+use utoipa::ToSchema;
 
-use super::dex::Dex;
+use super::{
+    dex::Dex,
+    revisions::{Revision, Revisions},
+};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, ToSchema)]
 pub struct Token {
     pub bech32: String,
     pub base16: String,
+    /// Score from the meta list; the list is sorted by it.
     pub scope: u8,
     pub name: String,
     pub symbol: String,
+    /// Token standard; synced ZRC-2 tokens are type 1.
     pub token_type: u8,
     pub decimals: u8,
+    /// Whether the token has a liquidity pool on the dex.
     pub listed: bool,
+    /// 1 when enabled, 0 when hidden.
     pub status: u8,
 }
 
@@ -45,15 +52,29 @@ pub struct ContractInit {
     pub field_type: String,
 }
 
+/// A token managed by hand, which the sync leaves alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Pin {
+    /// Added through the API.
+    Added,
+    /// Deleted through the API; the sync does not bring it back.
+    Deleted,
+}
+
 #[derive(Debug)]
 pub struct Meta {
     pub list: Vec<Token>,
+    pub revisions: Revisions,
+    /// Pinned tokens by lowercase address without `0x`.
+    pins: HashMap<String, Pin>,
+    pins_tree: Tree,
     db: Db,
     app_name: &'static str,
 }
 
 impl Meta {
-    pub fn new(db_path: &str) -> Self {
+    pub fn new(db_path: &str, max_revisions: usize) -> Self {
         let app_name = "META";
         let db =
             sled::open(format!("{}/{}", db_path, META_KEY)).expect("Cannot meta open database.");
@@ -72,7 +93,41 @@ impl Meta {
             });
 
         info!("{app_name}: loaded from cache {}", list.len());
-        Meta { list, db, app_name }
+
+        let revisions = Revisions::open(&db, max_revisions).expect("Cannot open meta revisions.");
+
+        // Lists cached before revisions existed become the first one.
+        if !list.is_empty() && matches!(revisions.head(), Ok(None)) {
+            if let Err(e) = revisions.append("cache", None, &list) {
+                error!("{app_name}: fail to record revision: {}", e);
+            }
+        }
+
+        let pins_tree = db
+            .open_tree(META_PINS_TREE)
+            .expect("Cannot open meta pins.");
+        let pins: HashMap<String, Pin> = pins_tree
+            .iter()
+            .filter_map(|item| {
+                let (key, value) = item.ok()?;
+
+                Some((
+                    String::from_utf8(key.to_vec()).ok()?,
+                    serde_json::from_slice(&value).ok()?,
+                ))
+            })
+            .collect();
+
+        info!("{app_name}: {} pinned tokens", pins.len());
+
+        Meta {
+            list,
+            revisions,
+            pins,
+            pins_tree,
+            db,
+            app_name,
+        }
     }
 
     pub fn update(
@@ -89,7 +144,9 @@ impl Meta {
                 let params = r.result.as_ref()?;
                 let (name, symbol, base16, decimals) = Meta::parse_init(params).ok()?;
 
-                if existing_base16s.contains(&base16.to_lowercase()) {
+                if existing_base16s.contains(&base16.to_lowercase())
+                    || self.pins.contains_key(&pin_key(&base16))
+                {
                     return None;
                 }
 
@@ -118,19 +175,205 @@ impl Meta {
         info!("{}: added new tokens {}", self.app_name, new_tokens.len());
 
         self.list.extend(new_tokens);
-        self.write_db()?;
 
         Ok(())
     }
 
-    pub fn write_db(&mut self) -> Result<(), Error> {
-        self.list.sort_by(|a, b| b.scope.cmp(&a.scope));
-        self.db.insert(META_KEY, self.serialization().as_bytes())?;
+    /// The token at `base16`, with or without `0x`, in any case.
+    pub fn get(&self, base16: &str) -> Option<&Token> {
+        let key = pin_key(base16);
+
+        self.list.iter().find(|t| pin_key(&t.base16) == key)
+    }
+
+    pub fn get_mut(&mut self, base16: &str) -> Option<&mut Token> {
+        let key = pin_key(base16);
+
+        self.list.iter_mut().find(|t| pin_key(&t.base16) == key)
+    }
+
+    pub fn pin(&self, base16: &str) -> Option<Pin> {
+        self.pins.get(&pin_key(base16)).copied()
+    }
+
+    /// Adds `token` by hand, pinned so the sync never drops or overwrites it.
+    pub fn insert_manual(&mut self, token: Token) -> Result<(), Error> {
+        self.set_pin(&token.base16, Pin::Added)?;
+        self.list.push(token);
+
         Ok(())
     }
 
+    /// Removes the token at `base16`, pinned as deleted so the sync does
+    /// not add it back. `None` when there is no such token.
+    pub fn remove_manual(&mut self, base16: &str) -> Result<Option<Token>, Error> {
+        let key = pin_key(base16);
+        let Some(index) = self.list.iter().position(|t| pin_key(&t.base16) == key) else {
+            return Ok(None);
+        };
+
+        self.set_pin(base16, Pin::Deleted)?;
+
+        Ok(Some(self.list.remove(index)))
+    }
+
+    fn set_pin(&mut self, base16: &str, pin: Pin) -> Result<(), Error> {
+        let key = pin_key(base16);
+
+        self.pins_tree
+            .insert(key.as_bytes(), serde_json::to_vec(&pin)?)?;
+        self.pins.insert(key, pin);
+
+        Ok(())
+    }
+
+    /// Puts the pins back to `pins`, in memory and, as far as it can, in
+    /// the database.
+    fn restore_pins(&mut self, pins: HashMap<String, Pin>) {
+        for key in self.pins.keys().filter(|key| !pins.contains_key(*key)) {
+            if let Err(e) = self.pins_tree.remove(key.as_bytes()) {
+                error!("{}: fail to restore pin {}: {}", self.app_name, key, e);
+            }
+        }
+        for (key, pin) in &pins {
+            let value = serde_json::to_vec(pin).unwrap_or_default();
+
+            if let Err(e) = self.pins_tree.insert(key.as_bytes(), value) {
+                error!("{}: fail to restore pin {}: {}", self.app_name, key, e);
+            }
+        }
+
+        self.pins = pins;
+    }
+
+    /// Stores the list, recording it as a new revision by `author` when it
+    /// differs from the latest one in more than `listed`. The sync flips
+    /// `listed` as pools come and go, and a revision per flip would soon
+    /// prune the admin ones.
+    pub fn write_db(&mut self, author: &str) -> Result<Option<Revision>, Error> {
+        self.store(author, None, same_but_listed)
+    }
+
+    /// Applies `edit`, stores the list as a new revision when it changed,
+    /// then runs
+    /// `record`, which writes the audit trail. When any of them fails, the
+    /// list, the pins and the revision are put back as they were, so
+    /// neither memory nor the database holds an edit the trail does not.
+    pub fn edit<T>(
+        &mut self,
+        author: &str,
+        edit: impl FnOnce(&mut Self) -> Result<T, Error>,
+        record: impl FnOnce() -> Result<(), Error>,
+    ) -> Result<(T, Option<Revision>), Error> {
+        self.commit(author, None, edit, record)
+    }
+
+    /// Restores revision `number`, recorded as a new revision, and drops
+    /// the pins it contradicts: a restored token is no longer deleted, and
+    /// a hand-added token the revision lacks is left to the sync again.
+    /// `None` when the revision does not exist (or was pruned). Nothing
+    /// changes when the rollback cannot be stored or `record` fails.
+    pub fn rollback(
+        &mut self,
+        number: u64,
+        author: &str,
+        record: impl FnOnce() -> Result<(), Error>,
+    ) -> Result<Option<Revision>, Error> {
+        let Some(target) = self.revisions.get(number)? else {
+            return Ok(None);
+        };
+        let (_, revision) = self.commit(
+            author,
+            Some(number),
+            |meta| {
+                let restored: HashSet<String> =
+                    target.tokens.iter().map(|t| pin_key(&t.base16)).collect();
+                let stale: Vec<String> = meta
+                    .pins
+                    .iter()
+                    .filter(|(key, pin)| (**pin == Pin::Deleted) == restored.contains(*key))
+                    .map(|(key, _)| key.clone())
+                    .collect();
+
+                for key in stale {
+                    meta.pins_tree.remove(key.as_bytes())?;
+                    meta.pins.remove(&key);
+                }
+
+                meta.list = target.tokens;
+
+                Ok(())
+            },
+            record,
+        )?;
+
+        info!("{}: rolled back to revision {}", self.app_name, number);
+
+        Ok(revision)
+    }
+
+    fn commit<T>(
+        &mut self,
+        author: &str,
+        rollback_of: Option<u64>,
+        edit: impl FnOnce(&mut Self) -> Result<T, Error>,
+        record: impl FnOnce() -> Result<(), Error>,
+    ) -> Result<(T, Option<Revision>), Error> {
+        let (list, pins) = (self.list.clone(), self.pins.clone());
+        let result = edit(self).and_then(|value| {
+            let revision = self.store(author, rollback_of, |a, b| a == b)?;
+
+            if let Err(e) = record() {
+                if let Some(revision) = &revision {
+                    if let Err(e) = self.revisions.remove(revision.number) {
+                        error!("{}: fail to drop revision: {}", self.app_name, e);
+                    }
+                }
+
+                return Err(e);
+            }
+
+            Ok((value, revision))
+        });
+
+        if result.is_err() {
+            self.list = list;
+            self.restore_pins(pins);
+
+            if let Err(e) = self.db.insert(META_KEY, self.serialization().as_bytes()) {
+                error!("{}: fail to restore the list: {}", self.app_name, e);
+            }
+        }
+
+        result
+    }
+
+    /// Stores the list and records it as a revision: always for a
+    /// rollback, otherwise unless it is the `same` as the latest one.
+    fn store(
+        &mut self,
+        author: &str,
+        rollback_of: Option<u64>,
+        same: fn(&[Token], &[Token]) -> bool,
+    ) -> Result<Option<Revision>, Error> {
+        self.list.sort_by_key(|t| std::cmp::Reverse(t.scope));
+        self.db.insert(META_KEY, self.serialization().as_bytes())?;
+
+        if rollback_of.is_none() {
+            if let Some(head) = self.revisions.head()? {
+                if same(&head.tokens, &self.list) {
+                    return Ok(None);
+                }
+            }
+        }
+
+        self.revisions
+            .append(author, rollback_of, &self.list)
+            .map(Some)
+    }
+
     pub async fn sort_zilliqa_tokens(
-        tokens: &Vec<(String, u8, String)>,
+        tokens: &[(String, u8, String)],
         zilliqa: &Zilliqa,
     ) -> Result<Vec<JsonBodyRes<Vec<ContractInit>>>, Error> {
         let bodies: Vec<JsonBodyReq> = tokens
@@ -144,10 +387,12 @@ impl Meta {
         Ok(results)
     }
 
-    pub async fn get_meta_tokens() -> Result<Vec<(String, u8, String)>, Error> {
-        Meta::fetch().await.map_err(|e| {
+    pub async fn get_meta_tokens(
+        settings: &MetaSettings,
+    ) -> Result<Vec<(String, u8, String)>, Error> {
+        Meta::fetch(settings).await.map_err(|e| {
             error!("Github is down!, error: {:?}", e);
-            Error::new(ErrorKind::Other, "Github is down")
+            Error::other("Github is down")
         })
     }
 
@@ -157,13 +402,17 @@ impl Meta {
         }
     }
 
+    pub fn flush(&self) -> Result<usize, Error> {
+        Ok(self.db.flush()?)
+    }
+
     pub fn serialization(&self) -> String {
         serde_json::to_string(&self.list).unwrap_or_default()
     }
 
-    async fn fetch() -> Result<Vec<(String, u8, String)>, reqwest::Error> {
+    async fn fetch(settings: &MetaSettings) -> Result<Vec<(String, u8, String)>, reqwest::Error> {
         let client = Client::new();
-        let response = client.get(CRYPTO_META_URL).send().await?;
+        let response = client.get(&settings.url).send().await?;
         let chain = "zilliqa.";
         let body: Map<String, Value> = response.json().await?;
 
@@ -177,7 +426,7 @@ impl Meta {
                 let bech32 = key.replace(chain, "");
                 let base16 = from_bech32_address(&bech32).map(hex::encode)?;
 
-                let found_exceptions = TOKENS_EXCEPTIONS.iter().find(|&addr| addr[0] == bech32);
+                let found_exceptions = settings.exceptions.iter().find(|&addr| addr[0] == bech32);
                 let score: u8 = value
                     .get("gen")
                     .and_then(|gen| gen.get("score"))
@@ -185,13 +434,13 @@ impl Meta {
                     .map(|s| s as u8)
                     .unwrap_or(0);
 
-                if score < MIN_SCORE {
+                if score < settings.min_score {
                     return None;
                 }
 
                 match found_exceptions {
                     Some(found) => from_bech32_address(&found[1])
-                        .map(|addr| (String::from(found[1]), score, hex::encode(addr))),
+                        .map(|addr| (found[1].clone(), score, hex::encode(addr))),
                     None => Some((bech32, score, base16)),
                 }
             })
@@ -200,7 +449,7 @@ impl Meta {
         Ok(body)
     }
 
-    fn parse_init(params: &Vec<ContractInit>) -> Result<(String, String, String, u8), Error> {
+    pub fn parse_init(params: &[ContractInit]) -> Result<(String, String, String, u8), Error> {
         let get_string_value = |vname: &str| -> Result<String, Error> {
             params
                 .iter()
@@ -208,9 +457,7 @@ impl Meta {
                 .and_then(|n| n.value.get("value"))
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string())
-                .ok_or_else(|| {
-                    Error::new(ErrorKind::Other, format!("vname ({}) is required", vname))
-                })
+                .ok_or_else(|| Error::other(format!("vname ({}) is required", vname)))
         };
 
         let name = get_string_value("name")?;
@@ -223,8 +470,220 @@ impl Meta {
             .and_then(|n| n.value.get("value"))
             .and_then(|v| v.as_str())
             .map(|s| s.parse::<u8>().unwrap_or(0))
-            .ok_or_else(|| Error::new(ErrorKind::Other, "vname (decimals) is required"))?;
+            .ok_or_else(|| Error::other("vname (decimals) is required"))?;
 
         Ok((name, symbol, base16, decimals))
     }
 }
+
+/// Whether `a` and `b` hold the same tokens in the same order, apart from
+/// their `listed` flags.
+fn same_but_listed(a: &[Token], b: &[Token]) -> bool {
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| {
+            a == &Token {
+                listed: a.listed,
+                ..b.clone()
+            }
+        })
+}
+
+fn pin_key(base16: &str) -> String {
+    let base16 = base16.to_lowercase();
+
+    base16.trim_start_matches("0x").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(base16: &str, symbol: &str) -> Token {
+        Token {
+            bech32: String::new(),
+            base16: base16.to_string(),
+            scope: 0,
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            token_type: 1,
+            decimals: 12,
+            listed: false,
+            status: 1,
+        }
+    }
+
+    fn init(base16: &str, symbol: &str) -> JsonBodyRes<Vec<ContractInit>> {
+        serde_json::from_value(json!({
+            "result": [
+                { "vname": "name", "type": "String", "value": symbol },
+                { "vname": "symbol", "type": "String", "value": symbol },
+                { "vname": "decimals", "type": "Uint32", "value": "12" },
+                { "vname": "_this_address", "type": "ByStr20", "value": base16 }
+            ],
+            "error": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sync_skips_pinned_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut meta = Meta::new(dir.path().to_str().unwrap(), 10);
+        let (added, deleted, synced) = (
+            "0x00000000000000000000000000000000000000aa",
+            "0x00000000000000000000000000000000000000bb",
+            "0x00000000000000000000000000000000000000cc",
+        );
+
+        meta.insert_manual(token(added, "MANUAL")).unwrap();
+        meta.list.push(token(deleted, "GONE"));
+        assert_eq!(
+            meta.remove_manual("0X00000000000000000000000000000000000000BB")
+                .unwrap()
+                .unwrap()
+                .symbol,
+            "GONE"
+        );
+        assert_eq!(meta.remove_manual(deleted).unwrap(), None);
+
+        let upstream = [added, deleted, synced]
+            .iter()
+            .map(|b16| (String::new(), 7, b16.replace("0x", "")))
+            .collect();
+        meta.update(
+            upstream,
+            vec![
+                init(added, "SYNCED"),
+                init(deleted, "GONE"),
+                init(synced, "NEW"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            meta.list
+                .iter()
+                .map(|t| t.symbol.as_str())
+                .collect::<Vec<_>>(),
+            vec!["MANUAL", "NEW"]
+        );
+        assert_eq!(meta.pin(added), Some(Pin::Added));
+
+        // Pins are persisted with the list. Reopening the database right
+        // after dropping it can race sled's flusher for the file lock, so
+        // the tree is read instead.
+        let stored = meta.pins_tree.get(pin_key(deleted)).unwrap().unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Pin>(&stored).unwrap(),
+            Pin::Deleted
+        );
+    }
+
+    #[test]
+    fn test_rollback_drops_contradicted_pins() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut meta = Meta::new(dir.path().to_str().unwrap(), 10);
+        let (deleted, added) = (
+            "0x00000000000000000000000000000000000000aa",
+            "0x00000000000000000000000000000000000000bb",
+        );
+
+        meta.list.push(token(deleted, "GONE"));
+        let before = meta.write_db("sync").unwrap().unwrap();
+        meta.edit("admin", |meta| meta.remove_manual(deleted), || Ok(()))
+            .unwrap();
+        meta.edit(
+            "admin",
+            |meta| meta.insert_manual(token(added, "NEW")),
+            || Ok(()),
+        )
+        .unwrap();
+
+        let revision = meta
+            .rollback(before.number, "admin", || Ok(()))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(revision.rollback_of, Some(before.number));
+        assert_eq!(meta.list, vec![token(deleted, "GONE")]);
+        // The restored token stays through the next sync, and the added
+        // one is no longer held back from it.
+        assert_eq!(meta.pin(deleted), None);
+        assert_eq!(meta.pin(added), None);
+        assert_eq!(meta.pins_tree.len(), 0);
+        assert!(meta.rollback(99, "admin", || Ok(())).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_listed_flips_are_no_revision() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut meta = Meta::new(dir.path().to_str().unwrap(), 10);
+        let base16 = "0x00000000000000000000000000000000000000aa";
+
+        meta.list.push(token(base16, "TKN"));
+        let first = meta.write_db("sync").unwrap().unwrap();
+
+        meta.list[0].listed = true;
+        assert!(meta.write_db("sync").unwrap().is_none());
+        assert_eq!(meta.revisions.head().unwrap().unwrap().number, first.number);
+        // The flip is stored all the same.
+        let stored: Vec<Token> =
+            serde_json::from_slice(&meta.db.get(META_KEY).unwrap().unwrap()).unwrap();
+        assert!(stored[0].listed);
+
+        meta.list[0].symbol = "NEW".to_string();
+        assert!(meta.write_db("sync").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_failed_edit_leaves_memory_untouched() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut meta = Meta::new(dir.path().to_str().unwrap(), 10);
+        let (kept, added) = (
+            "0x00000000000000000000000000000000000000aa",
+            "0x00000000000000000000000000000000000000bb",
+        );
+
+        meta.list.push(token(kept, "KEPT"));
+        meta.write_db("test").unwrap();
+
+        let result = meta.edit(
+            "admin",
+            |meta| {
+                meta.insert_manual(token(added, "ADDED"))?;
+                meta.get_mut(kept).unwrap().status = 0;
+
+                Err::<(), _>(Error::other("disk full"))
+            },
+            || Ok(()),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(meta.list, vec![token(kept, "KEPT")]);
+        assert_eq!(meta.pin(added), None);
+        assert_eq!(meta.pins_tree.len(), 0);
+
+        // A change the audit trail cannot record is taken back too.
+        let head = meta.revisions.head().unwrap().unwrap().number;
+        let result = meta.edit(
+            "admin",
+            |meta| meta.insert_manual(token(added, "ADDED")),
+            || Err(Error::other("audit full")),
+        );
+
+        assert!(result.is_err());
+        assert_eq!(meta.list, vec![token(kept, "KEPT")]);
+        assert_eq!(meta.pin(added), None);
+        assert_eq!(meta.revisions.head().unwrap().unwrap().number, head);
+
+        let (_, revision) = meta
+            .edit(
+                "admin",
+                |meta| meta.insert_manual(token(added, "ADDED")),
+                || Ok(()),
+            )
+            .unwrap();
+        assert_eq!(revision.map(|r| r.author), Some("admin".to_string()));
+        assert_eq!(meta.pin(added), Some(Pin::Added));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod audit;
+pub mod candles;
+pub mod changes;
 pub mod currencies;
 pub mod dex;
+pub mod history;
 pub mod meta;
+pub mod revisions;
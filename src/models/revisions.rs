@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sled::{Db, Tree};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Error,
+};
+use utoipa::ToSchema;
+
+use super::meta::Token;
+use crate::{config::meta::META_REVISIONS_TREE, utils::time::now_secs};
+
+/// Numbered snapshots of the token list, one per committed change.
+#[derive(Debug)]
+pub struct Revisions {
+    tree: Tree,
+    max: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Revision {
+    pub number: u64,
+    /// Unix seconds.
+    pub timestamp: u64,
+    /// `sync` for the upstream sync, otherwise the admin key id.
+    pub author: String,
+    /// The revision this one restored, when it is a rollback.
+    pub rollback_of: Option<u64>,
+    pub tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RevisionSummary {
+    pub number: u64,
+    pub timestamp: u64,
+    pub author: String,
+    pub rollback_of: Option<u64>,
+    /// Tokens in the revision.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RevisionDiff {
+    pub from: u64,
+    pub to: u64,
+    pub added: Vec<Token>,
+    pub removed: Vec<Token>,
+    pub changed: Vec<TokenDiff>,
+}
+
+/// Fields of one token that differ, keyed by field name.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct TokenDiff {
+    pub base16: String,
+    pub fields: BTreeMap<String, FieldDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldDiff {
+    #[schema(value_type = Object)]
+    pub from: Value,
+    #[schema(value_type = Object)]
+    pub to: Value,
+}
+
+impl Revisions {
+    /// Opens the revision tree of `db`, keeping at most `max` revisions.
+    pub fn open(db: &Db, max: usize) -> Result<Self, Error> {
+        Ok(Revisions {
+            tree: db.open_tree(META_REVISIONS_TREE)?,
+            max,
+        })
+    }
+
+    pub fn head(&self) -> Result<Option<Revision>, Error> {
+        self.tree
+            .last()?
+            .map(|(_, value)| Ok(serde_json::from_slice(&value)?))
+            .transpose()
+    }
+
+    pub fn get(&self, number: u64) -> Result<Option<Revision>, Error> {
+        self.tree
+            .get(number.to_be_bytes())?
+            .map(|value| Ok(serde_json::from_slice(&value)?))
+            .transpose()
+    }
+
+    /// The latest `limit` revisions, newest first.
+    pub fn list(&self, limit: usize) -> Result<Vec<RevisionSummary>, Error> {
+        self.tree
+            .iter()
+            .values()
+            .rev()
+            .take(limit)
+            .map(|value| {
+                let revision: Revision = serde_json::from_slice(&value?)?;
+
+                Ok(RevisionSummary {
+                    number: revision.number,
+                    timestamp: revision.timestamp,
+                    author: revision.author,
+                    rollback_of: revision.rollback_of,
+                    count: revision.tokens.len(),
+                })
+            })
+            .collect()
+    }
+
+    /// Stores `tokens` as the next revision, dropping the oldest ones past
+    /// the limit.
+    pub fn append(
+        &self,
+        author: &str,
+        rollback_of: Option<u64>,
+        tokens: &[Token],
+    ) -> Result<Revision, Error> {
+        let number = match self.tree.last()? {
+            Some((key, _)) => key_number(&key) + 1,
+            None => 1,
+        };
+        let revision = Revision {
+            number,
+            timestamp: now_secs(),
+            author: author.to_string(),
+            rollback_of,
+            tokens: tokens.to_vec(),
+        };
+
+        self.tree
+            .insert(number.to_be_bytes(), serde_json::to_vec(&revision)?)?;
+
+        while self.tree.len() > self.max {
+            if self.tree.pop_min()?.is_none() {
+                break;
+            }
+        }
+
+        Ok(revision)
+    }
+
+    /// Drops revision `number`, to take back one whose change was undone.
+    pub fn remove(&self, number: u64) -> Result<(), Error> {
+        self.tree.remove(number.to_be_bytes())?;
+
+        Ok(())
+    }
+}
+
+impl RevisionDiff {
+    /// Tokens matched by base16 address between `from` and `to`.
+    pub fn between(from: &Revision, to: &Revision) -> Self {
+        let key = |t: &Token| t.base16.to_lowercase();
+        let old: HashMap<String, &Token> = from.tokens.iter().map(|t| (key(t), t)).collect();
+        let new: HashMap<String, &Token> = to.tokens.iter().map(|t| (key(t), t)).collect();
+        let added = to
+            .tokens
+            .iter()
+            .filter(|t| !old.contains_key(&key(t)))
+            .cloned()
+            .collect();
+        let removed = from
+            .tokens
+            .iter()
+            .filter(|t| !new.contains_key(&key(t)))
+            .cloned()
+            .collect();
+        let changed = to
+            .tokens
+            .iter()
+            .filter_map(|token| {
+                let before = old.get(&key(token))?;
+                let fields = field_diff(before, token);
+
+                (!fields.is_empty()).then(|| TokenDiff {
+                    base16: key(token),
+                    fields,
+                })
+            })
+            .collect();
+
+        RevisionDiff {
+            from: from.number,
+            to: to.number,
+            added,
+            removed,
+            changed,
+        }
+    }
+}
+
+fn field_diff(from: &Token, to: &Token) -> BTreeMap<String, FieldDiff> {
+    let (Ok(Value::Object(from)), Ok(Value::Object(to))) =
+        (serde_json::to_value(from), serde_json::to_value(to))
+    else {
+        return BTreeMap::new();
+    };
+
+    to.into_iter()
+        .filter_map(|(field, value)| {
+            let old = from.get(&field).cloned().unwrap_or(Value::Null);
+
+            (old != value).then_some((
+                field,
+                FieldDiff {
+                    from: old,
+                    to: value,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn key_number(key: &[u8]) -> u64 {
+    key.try_into().map(u64::from_be_bytes).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn token(base16: &str, symbol: &str) -> Token {
+        Token {
+            bech32: String::new(),
+            base16: base16.to_string(),
+            scope: 0,
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            token_type: 1,
+            decimals: 12,
+            listed: false,
+            status: 1,
+        }
+    }
+
+    #[test]
+    fn test_append_and_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let revisions = Revisions::open(&db, 2).unwrap();
+
+        for symbol in ["A", "B", "C"] {
+            revisions
+                .append("sync", None, &[token("0x01", symbol)])
+                .unwrap();
+        }
+
+        let rollback = revisions
+            .append("ops", Some(2), &revisions.get(2).unwrap().unwrap().tokens)
+            .unwrap();
+
+        assert_eq!(rollback.number, 4);
+        assert_eq!(revisions.head().unwrap().unwrap().tokens[0].symbol, "B");
+        assert!(revisions.get(2).unwrap().is_none());
+        assert_eq!(
+            revisions
+                .list(10)
+                .unwrap()
+                .iter()
+                .map(|r| (r.number, r.rollback_of))
+                .collect::<Vec<_>>(),
+            vec![(4, Some(2)), (3, None)]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let revision = |number, tokens| Revision {
+            number,
+            timestamp: 0,
+            author: "sync".to_string(),
+            rollback_of: None,
+            tokens,
+        };
+        let mut renamed = token("0x02", "NEW");
+        renamed.status = 0;
+        let diff = RevisionDiff::between(
+            &revision(1, vec![token("0x01", "A"), token("0x02", "OLD")]),
+            &revision(2, vec![renamed, token("0x03", "C")]),
+        );
+
+        assert_eq!(diff.added[0].base16, "0x03");
+        assert_eq!(diff.removed[0].base16, "0x01");
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(
+            diff.changed[0].fields.keys().collect::<Vec<_>>(),
+            vec!["name", "status", "symbol"]
+        );
+        assert_eq!(
+            diff.changed[0].fields["status"],
+            FieldDiff {
+                from: json!(1),
+                to: json!(0)
+            }
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use log::warn;
+use serde_json::{Map, Value};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::RwLock;
+
+use super::{changed, Job, JobError, JobFuture, JobOutcome, Schedule};
+use crate::{
+    components::rates::WeightedProvider,
+    config::settings::{DexSettings, MetaSettings, RatesSettings},
+    models::{
+        candles::Candles,
+        changes::Changes,
+        currencies::{Currencies, Rates},
+        dex::Dex,
+        history::RatesHistory,
+        meta::{Meta, Token},
+    },
+    server::snapshot::Snapshots,
+    utils::{time::now_secs, zilliqa::Zilliqa},
+};
+
+/// Refresh intervals a price no source quotes is still served for.
+const CARRY_OVER_INTERVALS: u64 = 5;
+
+pub struct MetaJob {
+    pub meta: Arc<RwLock<Meta>>,
+    pub dex: Arc<RwLock<Dex>>,
+    pub snapshots: Arc<Snapshots>,
+    pub zilliqa: Zilliqa,
+    pub settings: MetaSettings,
+}
+
+pub struct RatesJob {
+    pub rates: Arc<RwLock<Currencies>>,
+    pub history: Arc<RatesHistory>,
+    pub candles: Arc<Candles>,
+    pub snapshots: Arc<Snapshots>,
+    pub providers: Vec<WeightedProvider>,
+    pub settings: RatesSettings,
+}
+
+pub struct DexJob {
+    pub dex: Arc<RwLock<Dex>>,
+    pub candles: Arc<Candles>,
+    pub snapshots: Arc<Snapshots>,
+    pub zilliqa: Zilliqa,
+    pub settings: DexSettings,
+}
+
+impl MetaJob {
+    pub fn schedule(&self) -> Schedule {
+        Schedule::from_secs(
+            self.settings.interval_secs,
+            self.settings.jitter_secs,
+            self.settings.max_backoff_secs,
+        )
+    }
+}
+
+impl RatesJob {
+    pub fn schedule(&self) -> Schedule {
+        Schedule::from_secs(
+            self.settings.interval_secs,
+            self.settings.jitter_secs,
+            self.settings.max_backoff_secs,
+        )
+    }
+}
+
+impl DexJob {
+    pub fn schedule(&self) -> Schedule {
+        Schedule::from_secs(
+            self.settings.interval_secs,
+            self.settings.jitter_secs,
+            self.settings.max_backoff_secs,
+        )
+    }
+}
+
+impl Job for MetaJob {
+    fn name(&self) -> &'static str {
+        "meta"
+    }
+
+    fn run(&self) -> JobFuture<'_> {
+        Box::pin(async move {
+            let tokens = Meta::get_meta_tokens(&self.settings)
+                .await
+                .map_err(|e| JobError::Upstream(format!("github:meta: {}", e)))?;
+            let sorted = Meta::sort_zilliqa_tokens(&tokens, &self.zilliqa)
+                .await
+                .map_err(|e| JobError::Upstream(format!("zilliqa node: {}", e)))?;
+            let mut meta = self.meta.write().await;
+            let before = meta.list.clone();
+
+            meta.update(tokens, sorted)
+                .map_err(|e| JobError::Storage(format!("tokens update: {}", e)))?;
+            meta.listed_tokens_update(&*self.dex.read().await);
+            meta.write_db("sync")
+                .map_err(|e| JobError::Storage(format!("tokens write: {}", e)))?;
+            self.snapshots
+                .publish_tokens(meta.list.clone())
+                .map_err(|e| JobError::Storage(format!("tokens snapshot: {}", e)))?;
+
+            let key = |t: &Token| t.base16.to_lowercase();
+
+            Ok(JobOutcome {
+                items: meta.list.len(),
+                changed: changed(
+                    before.iter().map(|t| (key(t), t)),
+                    meta.list.iter().map(|t| (key(t), t)),
+                ),
+            })
+        })
+    }
+}
+
+impl Job for RatesJob {
+    fn name(&self) -> &'static str {
+        "rates"
+    }
+
+    fn run(&self) -> JobFuture<'_> {
+        Box::pin(async move {
+            // With every source down the last prices are carried over all
+            // the same, so that they age out instead of being served forever.
+            let (mut rates, down) =
+                match Currencies::fetch_rates(&self.providers, &self.settings).await {
+                    Ok(rates) => (rates, None),
+                    Err(e) => (Rates::default(), Some(e)),
+                };
+            let mut currencies = self.rates.write().await;
+            let before = currencies.data.prices.0.clone();
+            let now = now_secs();
+            let kept = rates.carry_over(
+                &currencies.data,
+                &self.settings.currencies,
+                now,
+                self.settings.interval_secs * CARRY_OVER_INTERVALS,
+            );
+            let dropped = before
+                .keys()
+                .filter(|code| !rates.prices.contains_key(*code))
+                .count();
+
+            if kept > 0 {
+                warn!(
+                    "rates: no source quoted {} currencies, kept their last price",
+                    kept
+                );
+            }
+            if dropped > 0 {
+                warn!(
+                    "rates: dropped {} currencies no source quoted for too long",
+                    dropped
+                );
+            }
+
+            rates.changes = self
+                .history
+                .changes(now, &rates.prices)
+                .map_err(|e| JobError::Storage(format!("rates changes: {}", e)))?;
+            currencies
+                .update(rates)
+                .map_err(|e| JobError::Storage(format!("rates update: {}", e)))?;
+
+            if let Some(e) = down {
+                // Carried prices are not new observations: no history or
+                // candles from them.
+                self.snapshots
+                    .publish_rates(
+                        currencies.data.clone(),
+                        self.snapshots.load().token_changes.clone(),
+                    )
+                    .map_err(|e| JobError::Storage(format!("rates snapshot: {}", e)))?;
+
+                return Err(JobError::Upstream(format!("fetch rates: {}", e)));
+            }
+
+            self.history
+                .append(now, &currencies.data.prices)
+                .map_err(|e| JobError::Storage(format!("rates history: {}", e)))?;
+            let snapshot = self.snapshots.load();
+            let token_changes = update_candles(
+                &self.candles,
+                now,
+                &snapshot.tokens,
+                &snapshot.pools,
+                &currencies.data.prices,
+            )?;
+
+            self.snapshots
+                .publish_rates(currencies.data.clone(), token_changes)
+                .map_err(|e| JobError::Storage(format!("rates snapshot: {}", e)))?;
+
+            Ok(JobOutcome {
+                items: currencies.data.prices.len(),
+                changed: changed(before, currencies.data.prices.0.clone()),
+            })
+        })
+    }
+}
+
+impl Job for DexJob {
+    fn name(&self) -> &'static str {
+        "dex"
+    }
+
+    fn run(&self) -> JobFuture<'_> {
+        Box::pin(async move {
+            let pools = Dex::get_pools(&self.zilliqa, &self.settings.contract)
+                .await
+                .map_err(|e| JobError::Upstream(format!("fetch pools: {}", e)))?;
+            let mut dex = self.dex.write().await;
+            let before = dex.pools.clone();
+
+            dex.update(pools)
+                .map_err(|e| JobError::Storage(format!("pools update: {}", e)))?;
+            let snapshot = self.snapshots.load();
+            let token_changes = update_candles(
+                &self.candles,
+                now_secs(),
+                &snapshot.tokens,
+                &dex.pools,
+                &snapshot.rates.prices,
+            )?;
+
+            self.snapshots
+                .publish_pools(dex.pools.clone(), token_changes)
+                .map_err(|e| JobError::Storage(format!("pools snapshot: {}", e)))?;
+
+            Ok(JobOutcome {
+                items: dex.pools.len(),
+                changed: changed(before, dex.pools.clone()),
+            })
+        })
+    }
+}
+
+/// Folds the prices about to be published into the candles, and returns
+/// the token changes to publish with them.
+fn update_candles(
+    candles: &Candles,
+    now: u64,
+    tokens: &[Token],
+    pools: &HashMap<String, (u128, u128)>,
+    prices: &Map<String, Value>,
+) -> Result<HashMap<String, Changes>, JobError> {
+    candles
+        .update(now, tokens, pools, prices)
+        .map_err(|e| JobError::Storage(format!("candles update: {}", e)))?;
+
+    candles
+        .token_changes(now, tokens, pools)
+        .map_err(|e| JobError::Storage(format!("token changes: {}", e)))
+}
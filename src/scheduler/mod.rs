@@ -0,0 +1,432 @@
+use log::{error, info, warn};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{metrics, utils::time::now_secs};
+
+pub mod jobs;
+
+pub type JobFuture<'a> = Pin<Box<dyn Future<Output = Result<JobOutcome, JobError>> + Send + 'a>>;
+
+#[derive(Error, Debug, Clone)]
+pub enum JobError {
+    #[error("upstream error: {0}")]
+    Upstream(String),
+
+    #[error("storage error: {0}")]
+    Storage(String),
+}
+
+/// A background refresh that the scheduler runs on a fixed schedule.
+pub trait Job: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn run(&self) -> JobFuture<'_>;
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobOutcome {
+    /// Number of items held by the dataset after the run.
+    pub items: usize,
+    /// Items added, removed or modified by the run.
+    pub changed: usize,
+}
+
+/// One run of a job, shared with every caller that joined it.
+#[derive(Debug, Clone)]
+pub struct JobRun {
+    pub result: Result<JobOutcome, JobError>,
+    pub duration: Duration,
+    /// Whether the caller joined a run that was already in flight.
+    pub coalesced: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    pub interval: Duration,
+    pub jitter: Duration,
+    pub max_backoff: Duration,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub interval_secs: u64,
+    pub runs: u64,
+    pub failures: u64,
+    pub consecutive_failures: u32,
+    pub last_run: Option<u64>,
+    pub last_success: Option<u64>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+    pub items: Option<usize>,
+}
+
+pub struct JobHandle {
+    job: Box<dyn Job>,
+    schedule: Schedule,
+    status: RwLock<JobStatus>,
+    /// Held for the duration of a run.
+    running: tokio::sync::Mutex<()>,
+    last: Mutex<Option<JobRun>>,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: Vec<Arc<JobHandle>>,
+}
+
+impl Schedule {
+    pub fn from_secs(interval: u64, jitter: u64, max_backoff: u64) -> Self {
+        Schedule {
+            interval: Duration::from_secs(interval),
+            jitter: Duration::from_secs(jitter),
+            max_backoff: Duration::from_secs(max_backoff),
+        }
+    }
+
+    /// Delay before the next run: the interval, doubled per consecutive
+    /// failure up to `max_backoff`, plus a random jitter.
+    pub fn next_delay(&self, consecutive_failures: u32) -> Duration {
+        let base = if consecutive_failures == 0 {
+            self.interval
+        } else {
+            let factor = 2u32.saturating_pow(consecutive_failures.min(16));
+
+            self.interval
+                .saturating_mul(factor)
+                .min(self.max_backoff.max(self.interval))
+        };
+        let jitter_ms = self.jitter.as_millis() as u64;
+        let jitter = if jitter_ms == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_millis(fastrand::u64(0..=jitter_ms))
+        };
+
+        base + jitter
+    }
+}
+
+impl JobHandle {
+    pub fn name(&self) -> &'static str {
+        self.job.name()
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.status
+            .read()
+            .map(|s| s.clone())
+            .unwrap_or_else(|e| e.into_inner().clone())
+    }
+
+    pub async fn run_once(&self) -> Result<JobOutcome, JobError> {
+        self.trigger().await.result
+    }
+
+    /// Runs the job now, or, when a run is in flight, waits for it and
+    /// returns its result instead of starting another.
+    pub async fn trigger(&self) -> JobRun {
+        let seen = self.status().runs;
+        let _running = self.running.lock().await;
+
+        if self.status().runs != seen {
+            let last = self.last.lock().unwrap_or_else(|e| e.into_inner()).clone();
+
+            if let Some(run) = last {
+                return JobRun {
+                    coalesced: true,
+                    ..run
+                };
+            }
+        }
+
+        let run = self.execute().await;
+
+        *self.last.lock().unwrap_or_else(|e| e.into_inner()) = Some(run.clone());
+
+        run
+    }
+
+    async fn execute(&self) -> JobRun {
+        let started = Instant::now();
+        let started_at = now_secs();
+        let result = self.job.run().await;
+        let elapsed = started.elapsed();
+
+        metrics::observe_job(self.name(), result.is_ok(), elapsed);
+
+        let mut status = self.status.write().unwrap_or_else(|e| e.into_inner());
+
+        status.runs += 1;
+        status.last_run = Some(started_at);
+        status.last_duration_ms = Some(elapsed.as_millis() as u64);
+
+        match &result {
+            Ok(outcome) => {
+                status.consecutive_failures = 0;
+                status.last_success = Some(now_secs());
+                status.items = Some(outcome.items);
+
+                info!(
+                    "{}: refreshed {} items in {:?}",
+                    self.name(),
+                    outcome.items,
+                    elapsed
+                );
+            }
+            Err(e) => {
+                status.failures += 1;
+                status.consecutive_failures = status.consecutive_failures.saturating_add(1);
+                status.last_error = Some(e.to_string());
+                status.last_error_at = Some(now_secs());
+
+                error!("{}: {}", self.name(), e);
+            }
+        }
+
+        JobRun {
+            result,
+            duration: elapsed,
+            coalesced: false,
+        }
+    }
+
+    async fn run_until(self: Arc<Self>, shutdown: CancellationToken) {
+        loop {
+            let _ = self.run_once().await;
+
+            let failures = self.status().consecutive_failures;
+            let delay = self.schedule.next_delay(failures);
+
+            if failures > 0 {
+                warn!(
+                    "{}: {} consecutive failures, retrying in {:?}",
+                    self.name(),
+                    failures,
+                    delay
+                );
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = shutdown.cancelled() => {
+                    info!("{}: stopped", self.name());
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn add(&mut self, job: impl Job + 'static, schedule: Schedule) -> &mut Self {
+        let status = JobStatus {
+            name: job.name(),
+            interval_secs: schedule.interval.as_secs(),
+            ..Default::default()
+        };
+
+        self.jobs.push(Arc::new(JobHandle {
+            job: Box::new(job),
+            schedule,
+            status: RwLock::new(status),
+            running: tokio::sync::Mutex::new(()),
+            last: Mutex::new(None),
+        }));
+
+        self
+    }
+
+    pub fn job(&self, name: &str) -> Option<&Arc<JobHandle>> {
+        self.jobs.iter().find(|j| j.name() == name)
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs.iter().map(|j| j.status()).collect()
+    }
+
+    /// Spawns one task per job; each stops after its current run once
+    /// `shutdown` is cancelled.
+    pub fn spawn(&self, shutdown: CancellationToken) -> Vec<JoinHandle<()>> {
+        self.jobs
+            .iter()
+            .map(|job| tokio::task::spawn(Arc::clone(job).run_until(shutdown.clone())))
+            .collect()
+    }
+}
+
+/// Keys whose value differs between `before` and `after`, including keys
+/// present on one side only.
+pub fn changed<K: Eq + Hash, V: PartialEq>(
+    before: impl IntoIterator<Item = (K, V)>,
+    after: impl IntoIterator<Item = (K, V)>,
+) -> usize {
+    let mut before: HashMap<K, V> = before.into_iter().collect();
+    let mut changed = 0;
+
+    for (key, value) in after {
+        if before.remove(&key).is_none_or(|old| old != value) {
+            changed += 1;
+        }
+    }
+
+    changed + before.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct FlakyJob {
+        calls: AtomicUsize,
+    }
+
+    impl Job for FlakyJob {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn run(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                let call = self.calls.fetch_add(1, Ordering::SeqCst);
+
+                if call.is_multiple_of(2) {
+                    Err(JobError::Upstream(format!("call {}", call)))
+                } else {
+                    Ok(JobOutcome {
+                        items: call,
+                        changed: 0,
+                    })
+                }
+            })
+        }
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let schedule = Schedule::from_secs(10, 0, 60);
+
+        assert_eq!(schedule.next_delay(0), Duration::from_secs(10));
+        assert_eq!(schedule.next_delay(1), Duration::from_secs(20));
+        assert_eq!(schedule.next_delay(2), Duration::from_secs(40));
+        assert_eq!(schedule.next_delay(3), Duration::from_secs(60));
+        assert_eq!(schedule.next_delay(40), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_jitter_is_bounded() {
+        let schedule = Schedule::from_secs(10, 2, 60);
+
+        for _ in 0..100 {
+            let delay = schedule.next_delay(0);
+
+            assert!(delay >= Duration::from_secs(10));
+            assert!(delay <= Duration::from_secs(12));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_tracks_failures_and_success() {
+        let mut scheduler = Scheduler::new();
+
+        scheduler.add(
+            FlakyJob {
+                calls: AtomicUsize::new(0),
+            },
+            Schedule::from_secs(1, 0, 1),
+        );
+
+        let job = scheduler.job("flaky").unwrap();
+
+        assert!(job.run_once().await.is_err());
+
+        let status = job.status();
+        assert_eq!(status.consecutive_failures, 1);
+        assert_eq!(status.last_error.as_deref(), Some("upstream error: call 0"));
+        assert!(status.last_success.is_none());
+
+        assert_eq!(job.run_once().await.unwrap().items, 1);
+
+        let status = job.status();
+        assert_eq!(status.runs, 2);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.consecutive_failures, 0);
+        assert_eq!(status.items, Some(1));
+        assert!(status.last_success.is_some());
+        assert!(status.last_error.is_some());
+    }
+
+    struct SlowJob {
+        calls: AtomicUsize,
+    }
+
+    impl Job for SlowJob {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn run(&self) -> JobFuture<'_> {
+            Box::pin(async move {
+                let call = self.calls.fetch_add(1, Ordering::SeqCst);
+
+                tokio::time::sleep(Duration::from_millis(50)).await;
+
+                Ok(JobOutcome {
+                    items: call,
+                    changed: 1,
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trigger_joins_run_in_flight() {
+        let mut scheduler = Scheduler::new();
+
+        scheduler.add(
+            SlowJob {
+                calls: AtomicUsize::new(0),
+            },
+            Schedule::from_secs(1, 0, 1),
+        );
+
+        let job = scheduler.job("slow").unwrap();
+        let (first, second) = tokio::join!(job.trigger(), job.trigger());
+
+        assert!(!first.coalesced);
+        assert!(second.coalesced);
+        assert_eq!(second.result.unwrap().items, 0);
+        assert_eq!(job.status().runs, 1);
+
+        let third = job.trigger().await;
+        assert!(!third.coalesced);
+        assert_eq!(third.result.unwrap().items, 1);
+    }
+
+    #[test]
+    fn test_changed() {
+        let before = [("a", 1), ("b", 2), ("c", 3)];
+        let after = [("a", 1), ("b", 5), ("d", 4)];
+
+        assert_eq!(changed(before, after), 3);
+        assert_eq!(changed(before, before), 0);
+    }
+}
@@ -0,0 +1,170 @@
+use hyper::{header, Request};
+use log::warn;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::{error::ApiError, rate_limit::ClientAddr};
+use crate::config::settings::{Scope, Settings};
+
+/// Id of the key built from the legacy `server.access_token`.
+pub const LEGACY_KEY_ID: &str = "access_token";
+
+/// Admin keys, kept as SHA-256 digests of their secrets.
+pub struct AdminAuth {
+    keys: Vec<AdminKey>,
+}
+
+struct AdminKey {
+    id: String,
+    digest: [u8; 32],
+    scopes: Vec<Scope>,
+}
+
+/// The key a request was authenticated with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Admin {
+    pub id: String,
+}
+
+impl AdminAuth {
+    pub fn new(settings: &Settings) -> Self {
+        let mut keys = settings
+            .admin
+            .keys
+            .iter()
+            .filter_map(|key| {
+                let digest = hex::decode(&key.sha256).ok()?.try_into().ok()?;
+
+                Some(AdminKey {
+                    id: key.id.clone(),
+                    digest,
+                    scopes: key.scopes.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !settings.server.access_token.is_empty() {
+            keys.push(AdminKey {
+                id: LEGACY_KEY_ID.to_string(),
+                digest: Sha256::digest(&settings.server.access_token).into(),
+                // The legacy key predates scopes and could only edit tokens.
+                scopes: vec![Scope::TokensWrite],
+            });
+        }
+
+        AdminAuth { keys }
+    }
+
+    /// Checks the `Authorization` header, sent as `Bearer <secret>` or as the
+    /// bare secret, against every key, and requires `scope` of the match.
+    pub fn authorize<B>(&self, req: &Request<B>, scope: Scope) -> Result<Admin, ApiError> {
+        let from = match req.extensions().get::<ClientAddr>() {
+            Some(ClientAddr(ip)) => ip.to_string(),
+            None => "a local client".to_string(),
+        };
+        let secret = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.strip_prefix("Bearer ").unwrap_or(v).trim())
+            .filter(|v| !v.is_empty());
+        let Some(secret) = secret else {
+            warn!(
+                "admin: rejected {} request without a key from {}",
+                scope, from
+            );
+
+            return Err(ApiError::Unauthorized("Missing admin key".to_string()));
+        };
+        let digest: [u8; 32] = Sha256::digest(secret).into();
+        // Every key is compared so the timing does not reveal which one matched.
+        let mut found = None;
+
+        for key in &self.keys {
+            if bool::from(key.digest.ct_eq(&digest)) {
+                found = Some(key);
+            }
+        }
+
+        let Some(key) = found else {
+            warn!(
+                "admin: rejected {} request with an unknown key from {}",
+                scope, from
+            );
+
+            return Err(ApiError::Unauthorized("Invalid admin key".to_string()));
+        };
+
+        if !key.scopes.contains(&scope) {
+            warn!(
+                "admin: key `{}` lacks scope {} (from {})",
+                key.id, scope, from
+            );
+
+            return Err(ApiError::Forbidden(format!("Key lacks scope {}", scope)));
+        }
+
+        Ok(Admin { id: key.id.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::AdminKeySettings;
+
+    fn auth() -> AdminAuth {
+        let mut settings = Settings::default();
+
+        settings.server.access_token = "legacy".to_string();
+        settings.admin.keys = vec![AdminKeySettings {
+            id: "refresher".to_string(),
+            sha256: hex::encode(Sha256::digest("s3cret")),
+            scopes: vec![Scope::AdminRefresh],
+        }];
+
+        AdminAuth::new(&settings)
+    }
+
+    fn request(value: &'static str) -> Request<()> {
+        Request::builder()
+            .header(header::AUTHORIZATION, value)
+            .extension(ClientAddr("10.0.0.1".parse().unwrap()))
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_authorize() {
+        let auth = auth();
+
+        assert_eq!(
+            auth.authorize(&request("Bearer s3cret"), Scope::AdminRefresh)
+                .unwrap()
+                .id,
+            "refresher"
+        );
+        assert!(matches!(
+            auth.authorize(&request("s3cret"), Scope::TokensWrite),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert_eq!(
+            auth.authorize(&request("legacy"), Scope::TokensWrite)
+                .unwrap()
+                .id,
+            LEGACY_KEY_ID
+        );
+        assert!(matches!(
+            auth.authorize(&request("legacy"), Scope::AuditRead),
+            Err(ApiError::Forbidden(_))
+        ));
+        assert!(matches!(
+            auth.authorize(&request("Bearer nope"), Scope::AdminRefresh),
+            Err(ApiError::Unauthorized(_))
+        ));
+        assert!(matches!(
+            auth.authorize(&Request::new(()), Scope::AdminRefresh),
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    header::{self, HeaderMap, HeaderValue},
+    Response, StatusCode,
+};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, UNIX_EPOCH};
+
+use super::state::AppState;
+
+/// Max-age of responses built from compiled-in data.
+pub const STATIC_MAX_AGE_SECS: u64 = 3600;
+
+/// Freshness of one response: how long clients may reuse it and when its
+/// data last changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachePolicy {
+    pub max_age_secs: u64,
+    pub last_modified: Option<u64>,
+}
+
+/// Conditional headers of a request, copied before the handler consumes it.
+#[derive(Debug, Default, Clone)]
+pub struct Conditions {
+    if_none_match: Option<HeaderValue>,
+    if_modified_since: Option<HeaderValue>,
+}
+
+impl CachePolicy {
+    /// Fresh for the shortest interval among `jobs`, since any of them may
+    /// change the response; modified when their datasets were last
+    /// published, which admin edits do as well.
+    pub fn dataset(state: &AppState, jobs: &[&str]) -> Self {
+        let snapshot = state.snapshots.load();
+        let max_age_secs = jobs
+            .iter()
+            .filter_map(|name| state.scheduler.job(name))
+            .map(|job| job.status().interval_secs)
+            .min()
+            .unwrap_or(0);
+
+        CachePolicy {
+            max_age_secs,
+            last_modified: jobs
+                .iter()
+                .filter_map(|name| snapshot.published.get(name).copied())
+                .max(),
+        }
+    }
+
+    pub fn fixed(max_age_secs: u64) -> Self {
+        CachePolicy {
+            max_age_secs,
+            last_modified: None,
+        }
+    }
+
+    pub fn apply(&self, mut response: Response<Full<Bytes>>) -> Response<Full<Bytes>> {
+        let headers = response.headers_mut();
+
+        if let Ok(value) = HeaderValue::from_str(&format!("public, max-age={}", self.max_age_secs))
+        {
+            headers.insert(header::CACHE_CONTROL, value);
+        }
+        if let Some(secs) = self.last_modified {
+            let date = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs));
+
+            if let Ok(value) = HeaderValue::from_str(&date) {
+                headers.insert(header::LAST_MODIFIED, value);
+            }
+        }
+
+        response
+    }
+}
+
+impl Conditions {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        Conditions {
+            if_none_match: headers.get(header::IF_NONE_MATCH).cloned(),
+            if_modified_since: headers.get(header::IF_MODIFIED_SINCE).cloned(),
+        }
+    }
+
+    /// RFC 9110 §13.2.2: `If-None-Match` wins; `If-Modified-Since` is only
+    /// consulted without it.
+    fn not_modified(&self, etag: &str, last_modified: Option<&HeaderValue>) -> bool {
+        if let Some(value) = &self.if_none_match {
+            return value.to_str().is_ok_and(|v| etag_matches(v, etag));
+        }
+
+        let since = self
+            .if_modified_since
+            .as_ref()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+        let modified = last_modified
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok());
+
+        match (since, modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
+
+/// Weak validator over the body, stable across encodings of the same content.
+pub fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+
+    format!("W/\"{}\"", hex::encode(&digest[..16]))
+}
+
+/// Tags a successful response with its ETag, and swaps it for an empty 304
+/// when the client already holds this version. A pre-rendered body brings
+/// its own ETag, which is kept instead of hashing the body again.
+pub async fn revalidate(
+    conditions: &Conditions,
+    response: Response<Full<Bytes>>,
+) -> Response<Full<Bytes>> {
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(never) => match never {},
+    };
+    let tag = match parts
+        .headers
+        .get(header::ETAG)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(tag) => tag.to_string(),
+        None => etag(&body),
+    };
+    let mut response = Response::from_parts(parts, Full::new(body));
+
+    if conditions.not_modified(&tag, response.headers().get(header::LAST_MODIFIED)) {
+        let mut not_modified = Response::new(Full::new(Bytes::new()));
+
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+
+        for name in [header::CACHE_CONTROL, header::LAST_MODIFIED, header::VARY] {
+            if let Some(value) = response.headers().get(&name) {
+                not_modified.headers_mut().insert(name, value.clone());
+            }
+        }
+
+        response = not_modified;
+    }
+    if let Ok(value) = HeaderValue::from_str(&tag) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+
+    response
+}
+
+/// Weak comparison against a comma-separated `If-None-Match` list.
+fn etag_matches(header: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque(etag);
+
+    header
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || opaque(candidate) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(last_modified: Option<u64>) -> Response<Full<Bytes>> {
+        CachePolicy {
+            max_age_secs: 20,
+            last_modified,
+        }
+        .apply(Response::new(Full::new(Bytes::from_static(b"{}"))))
+    }
+
+    fn conditions(name: header::HeaderName, value: &str) -> Conditions {
+        let mut headers = HeaderMap::new();
+
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+
+        Conditions::from_headers(&headers)
+    }
+
+    #[test]
+    fn test_policy_headers() {
+        let response = ok(Some(1_700_000_000));
+
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=20"
+        );
+        assert_eq!(
+            response.headers()[header::LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_if_none_match() {
+        let tag = etag(b"{}");
+        let response = revalidate(&Conditions::default(), ok(None)).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], tag.as_str());
+
+        let strong = tag.trim_start_matches("W/");
+        let hit = conditions(header::IF_NONE_MATCH, &format!("\"other\", {}", strong));
+        let response = revalidate(&hit, ok(None)).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], tag.as_str());
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=20"
+        );
+
+        let miss = conditions(header::IF_NONE_MATCH, "W/\"other\"");
+        assert_eq!(revalidate(&miss, ok(None)).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_precomputed_etag_is_kept() {
+        let mut response = ok(None);
+
+        response
+            .headers_mut()
+            .insert(header::ETAG, HeaderValue::from_static("W/\"rendered\""));
+
+        let hit = conditions(header::IF_NONE_MATCH, "W/\"rendered\"");
+        let response = revalidate(&hit, response).await;
+
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], "W/\"rendered\"");
+    }
+
+    #[tokio::test]
+    async fn test_if_modified_since() {
+        let since = conditions(header::IF_MODIFIED_SINCE, "Tue, 14 Nov 2023 22:13:20 GMT");
+
+        assert_eq!(
+            revalidate(&since, ok(Some(1_700_000_000))).await.status(),
+            StatusCode::NOT_MODIFIED
+        );
+        assert_eq!(
+            revalidate(&since, ok(Some(1_700_000_001))).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(revalidate(&since, ok(None)).await.status(), StatusCode::OK);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Tue, 14 Nov 2023 22:13:20 GMT"),
+        );
+
+        assert_eq!(
+            revalidate(&Conditions::from_headers(&headers), ok(Some(1_700_000_000)))
+                .await
+                .status(),
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    header::{self, HeaderMap, HeaderValue},
+    Response, StatusCode,
+};
+use log::warn;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+    sync::Mutex,
+};
+
+use crate::config::settings::ServerSettings;
+
+const GZIP_LEVEL: u32 = 6;
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+/// Compresses response bodies for clients that accept it, keeping the
+/// output for bodies with an ETag since those repeat until the next update.
+pub struct Compressor {
+    min_bytes: usize,
+    capacity: usize,
+    cache: Mutex<Cache>,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<(String, Encoding), Bytes>,
+    order: VecDeque<(String, Encoding)>,
+}
+
+impl Encoding {
+    /// Server preference when the client weighs several codings equally.
+    const PREFERENCE: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Picks the coding with the highest `q` in an `Accept-Encoding` header.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+        let mut weights: HashMap<&str, f32> = HashMap::new();
+
+        for value in headers.get_all(header::ACCEPT_ENCODING) {
+            let Ok(value) = value.to_str() else {
+                continue;
+            };
+
+            for item in value.split(',') {
+                let mut parts = item.split(';');
+                let coding = parts.next().unwrap_or("").trim();
+                let q = parts
+                    .filter_map(|p| p.trim().strip_prefix("q="))
+                    .find_map(|q| q.trim().parse::<f32>().ok())
+                    .unwrap_or(1.0);
+
+                if !coding.is_empty() {
+                    weights.insert(coding, q);
+                }
+            }
+        }
+
+        let wildcard = weights.get("*").copied();
+        let mut best: Option<(Encoding, f32)> = None;
+
+        for encoding in Encoding::PREFERENCE {
+            let q = match weights.get(encoding.as_str()).copied().or(wildcard) {
+                Some(q) if q > 0.0 => q,
+                _ => continue,
+            };
+
+            if best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((encoding, q));
+            }
+        }
+
+        best.map(|(encoding, _)| encoding)
+    }
+
+    pub fn compress(&self, body: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::new(GZIP_LEVEL));
+
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                {
+                    let mut writer = brotli::CompressorWriter::new(
+                        &mut out,
+                        4096,
+                        BROTLI_QUALITY,
+                        BROTLI_WINDOW,
+                    );
+
+                    writer.write_all(body)?;
+                }
+
+                Ok(out)
+            }
+            Encoding::Zstd => zstd::bulk::compress(body, ZSTD_LEVEL),
+        }
+    }
+}
+
+impl Compressor {
+    pub fn new(settings: &ServerSettings) -> Self {
+        Compressor {
+            min_bytes: settings.compression_min_bytes,
+            capacity: settings.compression_cache_entries,
+            cache: Mutex::new(Cache::default()),
+        }
+    }
+
+    /// Marks compressible responses as varying by `Accept-Encoding` and
+    /// compresses large enough 200s with `encoding`.
+    pub async fn encode(
+        &self,
+        response: Response<Full<Bytes>>,
+        encoding: Option<Encoding>,
+    ) -> Response<Full<Bytes>> {
+        let not_modified = response.status() == StatusCode::NOT_MODIFIED;
+
+        if self.min_bytes == 0 || !(not_modified || compressible(response.headers())) {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+
+        // A 304 must repeat the Vary of the 200 it stands for.
+        parts
+            .headers
+            .insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+
+        let body = match body.collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(never) => match never {},
+        };
+        let encoding = match encoding {
+            Some(encoding) if parts.status == StatusCode::OK && body.len() >= self.min_bytes => {
+                encoding
+            }
+            _ => return Response::from_parts(parts, Full::new(body)),
+        };
+        let etag = parts
+            .headers
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+        let compressed = match self.compressed(etag, encoding, &body) {
+            Ok(compressed) => compressed,
+            Err(e) => {
+                warn!("compression: {} failed: {}", encoding.as_str(), e);
+
+                return Response::from_parts(parts, Full::new(body));
+            }
+        };
+
+        parts.headers.insert(
+            header::CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        parts.headers.remove(header::CONTENT_LENGTH);
+
+        Response::from_parts(parts, Full::new(compressed))
+    }
+
+    fn compressed(
+        &self,
+        etag: Option<String>,
+        encoding: Encoding,
+        body: &[u8],
+    ) -> io::Result<Bytes> {
+        let Some(etag) = etag.filter(|_| self.capacity > 0) else {
+            return encoding.compress(body).map(Bytes::from);
+        };
+        let key = (etag, encoding);
+
+        if let Some(hit) = self.lock().entries.get(&key) {
+            return Ok(hit.clone());
+        }
+
+        let compressed = Bytes::from(encoding.compress(body)?);
+        let mut cache = self.lock();
+
+        if cache
+            .entries
+            .insert(key.clone(), compressed.clone())
+            .is_none()
+        {
+            cache.order.push_back(key);
+        }
+        while cache.order.len() > self.capacity {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.entries.remove(&oldest);
+            }
+        }
+
+        Ok(compressed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn compressible(headers: &HeaderMap) -> bool {
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return false;
+    }
+
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|t| t.starts_with("application/json") || t.starts_with("text/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn accept(value: &str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_str(value).unwrap(),
+        );
+
+        Encoding::negotiate(&headers)
+    }
+
+    fn decompress(encoding: Encoding, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        match encoding {
+            Encoding::Gzip => {
+                flate2::read::GzDecoder::new(body)
+                    .read_to_end(&mut out)
+                    .unwrap();
+            }
+            Encoding::Brotli => {
+                brotli::Decompressor::new(body, 4096)
+                    .read_to_end(&mut out)
+                    .unwrap();
+            }
+            Encoding::Zstd => out = zstd::decode_all(body).unwrap(),
+        }
+
+        out
+    }
+
+    fn compressor(min_bytes: usize, capacity: usize) -> Compressor {
+        Compressor::new(&ServerSettings {
+            compression_min_bytes: min_bytes,
+            compression_cache_entries: capacity,
+            ..Default::default()
+        })
+    }
+
+    fn json(body: &'static [u8], etag: Option<&'static str>) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(Bytes::from_static(body)));
+
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        if let Some(etag) = etag {
+            response
+                .headers_mut()
+                .insert(header::ETAG, HeaderValue::from_static(etag));
+        }
+
+        response
+    }
+
+    async fn body(response: Response<Full<Bytes>>) -> Bytes {
+        response.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(accept("gzip, deflate, br, zstd"), Some(Encoding::Brotli));
+        assert_eq!(accept("gzip, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(accept("br;q=0, zstd"), Some(Encoding::Zstd));
+        assert_eq!(accept("*"), Some(Encoding::Brotli));
+        assert_eq!(accept("*;q=0.1, gzip;q=0.2"), Some(Encoding::Gzip));
+        assert_eq!(accept("identity"), None);
+        assert_eq!(Encoding::negotiate(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let body = br#"{"list":["zil","zlp","gzil","zil","zlp","gzil"]}"#.repeat(50);
+
+        for encoding in Encoding::PREFERENCE {
+            let compressed = encoding.compress(&body).unwrap();
+
+            assert!(compressed.len() < body.len(), "{:?}", encoding);
+            assert_eq!(decompress(encoding, &compressed), body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_threshold_and_vary() {
+        let compressor = compressor(16, 4);
+        let small = compressor
+            .encode(json(b"{}", None), Some(Encoding::Gzip))
+            .await;
+
+        assert_eq!(small.headers()[header::VARY], "Accept-Encoding");
+        assert!(!small.headers().contains_key(header::CONTENT_ENCODING));
+
+        let large = compressor
+            .encode(json(b"[1,2,3,4,5,6,7,8,9,10]", None), Some(Encoding::Zstd))
+            .await;
+
+        assert_eq!(large.headers()[header::CONTENT_ENCODING], "zstd");
+        assert_eq!(
+            decompress(Encoding::Zstd, &body(large).await),
+            b"[1,2,3,4,5,6,7,8,9,10]"
+        );
+
+        let off = self::compressor(0, 4)
+            .encode(json(b"[1,2,3,4,5,6,7,8,9,10]", None), Some(Encoding::Gzip))
+            .await;
+
+        assert!(!off.headers().contains_key(header::VARY));
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_bounded() {
+        let compressor = compressor(1, 2);
+
+        for etag in ["\"a\"", "\"b\"", "\"c\""] {
+            compressor
+                .encode(json(b"[1,2,3]", Some(etag)), Some(Encoding::Gzip))
+                .await;
+        }
+        compressor
+            .encode(json(b"[1,2,3]", Some("\"c\"")), Some(Encoding::Gzip))
+            .await;
+
+        let cache = compressor.lock();
+
+        assert_eq!(cache.entries.len(), 2);
+        assert!(!cache
+            .entries
+            .contains_key(&("\"a\"".to_string(), Encoding::Gzip)));
+    }
+}
@@ -0,0 +1,224 @@
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    header::{self, HeaderValue},
+    Method, Response, StatusCode,
+};
+use log::error;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+pub type ApiResult = Result<Response<Full<Bytes>>, ApiError>;
+
+/// Every failure a handler can report, rendered as one JSON envelope.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String, Option<Value>),
+
+    #[error("{0}")]
+    Unauthorized(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("Method not allowed")]
+    MethodNotAllowed(Vec<Method>),
+
+    /// Seconds until the client's bucket holds a token again.
+    #[error("Too many requests, retry in {0}s")]
+    TooManyRequests(u64),
+
+    #[error("Cannot read request body: {0}")]
+    Body(#[from] hyper::Error),
+
+    /// A node or API the request depends on failed.
+    #[error("{0}")]
+    Upstream(String),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody<'a> {
+    /// One of `bad_request`, `invalid_body`, `unauthorized`, `forbidden`,
+    /// `not_found`, `conflict`, `method_not_allowed`, `rate_limited`,
+    /// `upstream_error` or `internal_error`.
+    #[schema(example = "not_found")]
+    code: &'static str,
+    message: &'a str,
+    #[schema(value_type = Option<Object>)]
+    details: Option<&'a Value>,
+}
+
+impl ApiError {
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::BadRequest(message.into(), None)
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(..) | ApiError::Body(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable machine-readable identifier, independent of the message wording.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(..) => "bad_request",
+            ApiError::Body(_) => "invalid_body",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::MethodNotAllowed(_) => "method_not_allowed",
+            ApiError::TooManyRequests(_) => "rate_limited",
+            ApiError::Upstream(_) => "upstream_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn into_response(self) -> Response<Full<Bytes>> {
+        let status = self.status();
+        // Internal details go to the log, not to clients.
+        let message = match &self {
+            ApiError::Internal(e) => {
+                error!("api: {}", e);
+
+                "Internal server error".to_string()
+            }
+            e => e.to_string(),
+        };
+        let details = match &self {
+            ApiError::BadRequest(_, details) => details.as_ref(),
+            _ => None,
+        };
+        let body = ErrorBody {
+            code: self.code(),
+            message: &message,
+            details,
+        };
+        let json = serde_json::to_vec(&body).unwrap_or_else(|_| {
+            br#"{"code":"internal_error","message":"Internal server error","details":null}"#
+                .to_vec()
+        });
+        let mut response = bytes_response(status, "application/json", json);
+
+        match &self {
+            ApiError::MethodNotAllowed(allowed) => {
+                response
+                    .headers_mut()
+                    .insert(header::ALLOW, allow_header(allowed));
+            }
+            ApiError::TooManyRequests(secs) => {
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(*secs));
+            }
+            _ => {}
+        }
+
+        response
+    }
+}
+
+/// Serializes `value` as the response body.
+pub fn json<T: Serialize + ?Sized>(status: StatusCode, value: &T) -> ApiResult {
+    let body = serde_json::to_vec(value)
+        .map_err(|e| ApiError::Internal(format!("serialize response: {}", e)))?;
+
+    Ok(bytes_response(status, "application/json", body))
+}
+
+pub fn bytes_response(
+    status: StatusCode,
+    content_type: &'static str,
+    body: impl Into<Bytes>,
+) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(body.into()));
+
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+    response
+}
+
+/// Value of the `Allow` header: the route's methods plus the implicit `OPTIONS`.
+pub fn allow_header(allowed: &[Method]) -> HeaderValue {
+    let methods = allowed
+        .iter()
+        .map(Method::as_str)
+        .chain(std::iter::once("OPTIONS"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    HeaderValue::from_str(&methods).unwrap_or_else(|_| HeaderValue::from_static("OPTIONS"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use serde_json::json;
+
+    async fn body(response: Response<Full<Bytes>>) -> Value {
+        let bytes = response.into_body().collect().await.unwrap().to_bytes();
+
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_envelope_shape() {
+        let err = ApiError::BadRequest(
+            "Invalid field".to_string(),
+            Some(json!({ "field": "score" })),
+        );
+        let response = err.into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            body(response).await,
+            json!({
+                "code": "bad_request",
+                "message": "Invalid field",
+                "details": { "field": "score" }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_internal_errors_are_not_leaked() {
+        let response = ApiError::Internal("sled: disk on fire".to_string()).into_response();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body(response).await["message"], "Internal server error");
+    }
+
+    #[test]
+    fn test_method_not_allowed_sets_allow() {
+        let response = ApiError::MethodNotAllowed(vec![Method::GET]).into_response();
+
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[header::ALLOW], "GET, OPTIONS");
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use tokio::net::{TcpListener, TcpStream};
+
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::config::settings::ListenerSettings;
+
+pub enum Listener {
+    Tcp(TcpListener, SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+pub enum Accepted {
+    Tcp(TcpStream, SocketAddr),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Listener {
+    pub async fn bind(settings: &ListenerSettings) -> Result<Self, io::Error> {
+        match (&settings.address, &settings.unix) {
+            (Some(address), None) => {
+                let addr: SocketAddr = address
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                let listener = TcpListener::bind(addr).await?;
+                let addr = listener.local_addr()?;
+
+                Ok(Listener::Tcp(listener, addr))
+            }
+            #[cfg(unix)]
+            (None, Some(path)) => {
+                let path = PathBuf::from(path);
+
+                remove_stale_socket(&path)?;
+
+                Ok(Listener::Unix(UnixListener::bind(&path)?, path))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "listener needs exactly one of address or unix",
+            )),
+        }
+    }
+
+    pub async fn accept(&self) -> Result<Accepted, io::Error> {
+        match self {
+            Listener::Tcp(listener, _) => {
+                let (stream, addr) = listener.accept().await?;
+
+                Ok(Accepted::Tcp(stream, addr))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener, _) => {
+                let (stream, _) = listener.accept().await?;
+
+                Ok(Accepted::Unix(stream))
+            }
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match self {
+            Listener::Tcp(_, addr) => format!("http://{}", addr),
+            #[cfg(unix)]
+            Listener::Unix(_, path) => format!("unix:{}", path.display()),
+        }
+    }
+}
+
+/// Unlinks a socket file left behind by a previous run, which would block
+/// bind. Anything else at `path` is left alone and refused.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<(), io::Error> {
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path),
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        )),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(_, path) = self {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unix_bind_keeps_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        let settings = ListenerSettings {
+            unix: Some(path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        std::fs::write(&path, "data").unwrap();
+        assert!(Listener::bind(&settings).await.is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+
+        // A stale socket is replaced.
+        std::fs::remove_file(&path).unwrap();
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(Listener::bind(&settings).await.is_ok());
+    }
+}
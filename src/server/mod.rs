@@ -1,47 +1,217 @@
 use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
-use hyper_util::server::conn::auto;
-use log::{error, info};
-use std::{io, net::SocketAddr, sync::Arc};
-use tokio::net::TcpListener;
-use tokio::sync::RwLock;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::{conn::auto, graceful::GracefulShutdown};
+use log::{error, info, warn};
+use std::{io, net::IpAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    task::JoinSet,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
 
+use listener::{Accepted, Listener};
 use routers::route;
+use state::AppState;
+use tls::CertResolver;
 
-use crate::models::{currencies::Currencies, dex::Dex, meta::Meta};
+use crate::config::settings::ListenerSettings;
 
+pub mod auth;
+pub mod cache;
+pub mod compression;
+pub mod error;
+pub mod listener;
+pub mod rate_limit;
+pub mod router;
 mod routers;
+pub mod signal;
+pub mod snapshot;
+pub mod state;
+pub mod tls;
 
-pub async fn run_server(
-    meta: &Arc<RwLock<Meta>>,
-    dex: &Arc<RwLock<Dex>>,
-    rates: &Arc<RwLock<Currencies>>,
-    port: u16,
-) -> Result<(), io::Error> {
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
-    let listener = TcpListener::bind(&addr).await?;
+/// Clients that have not finished the TLS handshake by then are dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Bounds of the pause after a failed accept, e.g. when out of file
+/// descriptors; it doubles while accepting keeps failing.
+const ACCEPT_BACKOFF: (Duration, Duration) = (Duration::from_millis(10), Duration::from_secs(1));
 
-    info!("Listening on http://{}", addr);
+/// Serves requests on every configured listener until `shutdown` is
+/// cancelled, then waits up to `server.shutdown_timeout_secs` for in-flight
+/// connections.
+pub async fn run_server(state: AppState, shutdown: CancellationToken) -> Result<(), io::Error> {
+    let timeout = Duration::from_secs(state.settings.server.shutdown_timeout_secs);
+    let graceful = Arc::new(GracefulShutdown::new());
+    let mut tasks = Vec::new();
+    let mut resolvers = Vec::new();
+
+    for settings in state.settings.server.listeners() {
+        let acceptor = match &settings.tls {
+            Some(tls) => {
+                let resolver = Arc::new(
+                    CertResolver::load(tls)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+                );
+                let acceptor = tls::acceptor(Arc::clone(&resolver), &settings)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+                resolvers.push(resolver);
+
+                Some(acceptor)
+            }
+            None => None,
+        };
+        let listener = Listener::bind(&settings).await?;
+        let url = match &acceptor {
+            Some(_) => listener.url().replacen("http://", "https://", 1),
+            None => listener.url(),
+        };
+
+        info!(
+            "Listening on {} (http1: {}, http2: {})",
+            url, settings.http1, settings.http2
+        );
+
+        tasks.push(tokio::task::spawn(accept_loop(
+            listener,
+            acceptor,
+            builder(&settings),
+            Arc::clone(&graceful),
+            state.clone(),
+            shutdown.clone(),
+        )));
+    }
+
+    if !resolvers.is_empty() {
+        tokio::task::spawn(signal::hangup(shutdown.clone(), move || {
+            for resolver in &resolvers {
+                match resolver.reload() {
+                    Ok(()) => info!("Reloaded TLS certificate {}", resolver.cert_path()),
+                    Err(e) => error!("Keeping previous TLS certificate: {}", e),
+                }
+            }
+        }));
+    }
+
+    for task in tasks {
+        if let Err(e) = task.await {
+            error!("listener task failed: {}", e);
+        }
+    }
+
+    info!(
+        "Stopped accepting connections, draining for up to {:?}",
+        timeout
+    );
+
+    let Ok(graceful) = Arc::try_unwrap(graceful) else {
+        warn!("Cannot drain connections, listener still running");
+
+        return Ok(());
+    };
+
+    tokio::select! {
+        _ = graceful.shutdown() => info!("All connections closed"),
+        _ = tokio::time::sleep(timeout) => warn!("Timed out waiting for connections to close"),
+    }
+
+    Ok(())
+}
+
+fn builder(settings: &ListenerSettings) -> auto::Builder<TokioExecutor> {
+    let builder = auto::Builder::new(TokioExecutor::new());
+
+    match (settings.http1, settings.http2) {
+        (true, false) => builder.http1_only(),
+        (false, true) => builder.http2_only(),
+        _ => builder,
+    }
+}
+
+async fn accept_loop(
+    listener: Listener,
+    acceptor: Option<TlsAcceptor>,
+    builder: auto::Builder<TokioExecutor>,
+    graceful: Arc<GracefulShutdown>,
+    state: AppState,
+    shutdown: CancellationToken,
+) {
+    // Handshakes run off the accept loop so a slow client cannot stall it;
+    // pending ones are aborted on shutdown.
+    let mut handshakes = JoinSet::new();
+    let mut backoff = ACCEPT_BACKOFF.0;
 
     loop {
-        let (stream, _) = listener.accept().await?;
-        let meta_ref = Arc::clone(&meta);
-        let dex_ref = Arc::clone(&dex);
-        let rates_ref = Arc::clone(&rates);
-
-        tokio::task::spawn(async move {
-            let service = service_fn(move |req| {
-                route(req, meta_ref.clone(), dex_ref.clone(), rates_ref.clone())
-            });
-
-            let io = TokioIo::new(stream);
-
-            if let Err(err) = auto::Builder::new(hyper_util::rt::TokioExecutor::new())
-                .serve_connection(io, service)
-                .await
-            {
-                error!("Failed to serve connection: {:?}", err);
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            Some(_) = handshakes.join_next() => continue,
+            _ = shutdown.cancelled() => break,
+        };
+        let accepted = match accepted {
+            Ok(accepted) => {
+                backoff = ACCEPT_BACKOFF.0;
+
+                accepted
+            }
+            Err(e) => {
+                error!("Failed to accept connection on {}: {:?}", listener.url(), e);
+
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = shutdown.cancelled() => break,
+                }
+                backoff = (backoff * 2).min(ACCEPT_BACKOFF.1);
+
+                continue;
+            }
+        };
+
+        match (accepted, &acceptor) {
+            (Accepted::Tcp(stream, addr), Some(acceptor)) => {
+                let handshake = acceptor.accept(stream);
+                let builder = builder.clone();
+                let graceful = Arc::clone(&graceful);
+                let state = state.clone();
+
+                handshakes.spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, handshake).await {
+                        Ok(Ok(stream)) => {
+                            serve(stream, Some(addr.ip()), &builder, &graceful, state)
+                        }
+                        Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => warn!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+            (Accepted::Tcp(stream, addr), None) => {
+                serve(stream, Some(addr.ip()), &builder, &graceful, state.clone())
             }
-        });
+            #[cfg(unix)]
+            (Accepted::Unix(stream), _) => serve(stream, None, &builder, &graceful, state.clone()),
+        }
     }
+
+    handshakes.shutdown().await;
+}
+
+fn serve<IO>(
+    io: IO,
+    peer: Option<IpAddr>,
+    builder: &auto::Builder<TokioExecutor>,
+    graceful: &GracefulShutdown,
+    state: AppState,
+) where
+    IO: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let service = service_fn(move |req| route(req, peer, state.clone()));
+    let conn = builder
+        .serve_connection(TokioIo::new(io), service)
+        .into_owned();
+    let conn = graceful.watch(conn);
+
+    tokio::task::spawn(async move {
+        if let Err(err) = conn.await {
+            error!("Failed to serve connection: {:?}", err);
+        }
+    });
 }
@@ -0,0 +1,413 @@
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use lru::LruCache;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv6Addr},
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::Instant,
+};
+
+use super::error::ApiError;
+use crate::config::settings::RateLimitSettings;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const X_RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const X_RATELIMIT_RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// Most buckets kept at once; the least recently used one makes room.
+const MAX_BUCKETS: NonZeroUsize = NonZeroUsize::new(100_000).unwrap();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Quota {
+    rate: f64,
+    burst: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    Key(String),
+    Ip(IpAddr),
+}
+
+/// Outcome of one admission, reported in the `X-RateLimit-*` headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset_secs: u64,
+    /// Seconds until the next request would be admitted; 0 when allowed.
+    pub retry_after_secs: u64,
+}
+
+/// Client address of a request, stored in its extensions for logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientAddr(pub IpAddr);
+
+/// Token buckets keyed by client and route, see `RateLimitSettings`.
+pub struct RateLimiter {
+    enabled: bool,
+    trusted_hops: usize,
+    default: Quota,
+    routes: HashMap<String, Quota>,
+    /// Quota and name of every API key, keyed by the key itself.
+    keys: HashMap<String, (String, Quota)>,
+    buckets: Mutex<Buckets>,
+}
+
+/// Buckets from least to most recently used, so that both dropping the
+/// refilled ones and making room only ever look at the oldest.
+struct Buckets {
+    entries: LruCache<(Client, &'static str), Bucket>,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    quota: Quota,
+}
+
+impl Decision {
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        headers.insert(X_RATELIMIT_LIMIT, HeaderValue::from(self.limit));
+        headers.insert(X_RATELIMIT_REMAINING, HeaderValue::from(self.remaining));
+        headers.insert(X_RATELIMIT_RESET, HeaderValue::from(self.reset_secs));
+    }
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        RateLimiter {
+            enabled: settings.enabled,
+            trusted_hops: settings.trusted_hops,
+            default: Quota {
+                rate: settings.rate,
+                burst: settings.burst,
+            },
+            routes: settings
+                .routes
+                .iter()
+                .map(|r| {
+                    let quota = Quota {
+                        rate: r.rate,
+                        burst: r.burst,
+                    };
+
+                    (r.pattern.clone(), quota)
+                })
+                .collect(),
+            keys: settings
+                .keys
+                .iter()
+                .map(|k| {
+                    let quota = Quota {
+                        rate: k.rate,
+                        burst: k.burst,
+                    };
+
+                    (k.key.clone(), (k.name.clone(), quota))
+                })
+                .collect(),
+            buckets: Mutex::new(Buckets {
+                entries: LruCache::new(MAX_BUCKETS),
+            }),
+        }
+    }
+
+    /// The address a request came from, looking through `trusted_hops`
+    /// proxies; `None` for local unix socket peers.
+    pub fn client(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        client_ip(headers, peer, self.trusted_hops)
+    }
+
+    /// Takes a token for a request to the route `pattern`, or `None` when
+    /// the request is not limited. An unknown API key is rejected instead
+    /// of falling back to the anonymous quota.
+    pub fn admit(
+        &self,
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+        pattern: &'static str,
+    ) -> Result<Option<Decision>, ApiError> {
+        self.admit_at(headers, peer, pattern, Instant::now())
+    }
+
+    fn admit_at(
+        &self,
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+        pattern: &'static str,
+        now: Instant,
+    ) -> Result<Option<Decision>, ApiError> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let (client, scope, quota) = match headers.get(API_KEY_HEADER) {
+            Some(value) => {
+                let (name, quota) = value
+                    .to_str()
+                    .ok()
+                    .and_then(|key| self.keys.get(key))
+                    .ok_or_else(|| ApiError::Unauthorized("Unknown API key".to_string()))?;
+
+                (Client::Key(name.clone()), "*", *quota)
+            }
+            None => {
+                // Unix socket peers without a forwarded address are local.
+                let Some(ip) = client_ip(headers, peer, self.trusted_hops) else {
+                    return Ok(None);
+                };
+
+                match self.routes.get(pattern) {
+                    Some(quota) if quota.rate == 0.0 => return Ok(None),
+                    Some(quota) => (Client::ip(ip), pattern, *quota),
+                    None => (Client::ip(ip), "*", self.default),
+                }
+            }
+        };
+
+        Ok(Some(self.take(client, scope, quota, now)))
+    }
+
+    fn take(&self, client: Client, scope: &'static str, quota: Quota, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        buckets.sweep(now);
+
+        // At capacity, a new bucket evicts the least recently used one.
+        let bucket = buckets
+            .entries
+            .get_or_insert_mut((client, scope), || Bucket {
+                tokens: f64::from(quota.burst),
+                updated: now,
+                quota,
+            });
+
+        bucket.refill(now);
+
+        let allowed = bucket.tokens >= 1.0;
+
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        let secs_until = |tokens: f64| (tokens.max(0.0) / quota.rate).ceil() as u64;
+
+        Decision {
+            allowed,
+            limit: quota.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: secs_until(f64::from(quota.burst) - bucket.tokens),
+            retry_after_secs: if allowed {
+                0
+            } else {
+                secs_until(1.0 - bucket.tokens).max(1)
+            },
+        }
+    }
+}
+
+impl Buckets {
+    /// Drops the least recently used buckets while they have refilled
+    /// completely, as a fresh bucket would be the same. Each bucket is
+    /// dropped once, so this is constant time per request on average.
+    fn sweep(&mut self, now: Instant) {
+        while let Some((_, bucket)) = self.entries.peek_lru() {
+            let mut bucket = *bucket;
+
+            bucket.refill(now);
+
+            if bucket.tokens < f64::from(bucket.quota.burst) {
+                break;
+            }
+
+            self.entries.pop_lru();
+        }
+    }
+}
+
+impl Client {
+    /// An IPv6 host usually owns its whole /64, so it shares one bucket.
+    fn ip(ip: IpAddr) -> Self {
+        match ip.to_canonical() {
+            IpAddr::V6(v6) => Client::Ip(IpAddr::V6(Ipv6Addr::from(
+                u128::from(v6) & (u128::MAX << 64),
+            ))),
+            v4 => Client::Ip(v4),
+        }
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.quota.rate).min(f64::from(self.quota.burst));
+        self.updated = now;
+    }
+}
+
+/// The address seen by the outermost of `trusted_hops` proxies; entries
+/// left of it are client-supplied and ignored.
+fn client_ip(headers: &HeaderMap, peer: Option<IpAddr>, trusted_hops: usize) -> Option<IpAddr> {
+    if trusted_hops == 0 {
+        return peer;
+    }
+
+    let hops = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|hop| !hop.is_empty())
+        .collect::<Vec<_>>();
+
+    hops.len()
+        .checked_sub(trusted_hops)
+        .and_then(|i| hops[i].parse().ok())
+        .or(peer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{ApiKeyLimit, RouteLimit};
+    use std::time::Duration;
+
+    const PEER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1));
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(&RateLimitSettings {
+            rate: 1.0,
+            burst: 2,
+            routes: vec![
+                RouteLimit {
+                    pattern: "/api/v1/dex".to_string(),
+                    rate: 0.5,
+                    burst: 1,
+                },
+                RouteLimit {
+                    pattern: "/health".to_string(),
+                    rate: 0.0,
+                    burst: 0,
+                },
+            ],
+            keys: vec![ApiKeyLimit {
+                name: "wallet".to_string(),
+                key: "k1".to_string(),
+                rate: 10.0,
+                burst: 5,
+            }],
+            ..Default::default()
+        })
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (HeaderName::from_static(k), HeaderValue::from_static(v)))
+            .collect()
+    }
+
+    #[test]
+    fn test_bucket_drains_and_refills() {
+        let limiter = limiter();
+        let start = Instant::now();
+        let admit = |at: Instant| {
+            limiter
+                .admit_at(&HeaderMap::new(), Some(PEER), "/api/v1/rates", at)
+                .unwrap()
+                .unwrap()
+        };
+
+        assert_eq!(admit(start).remaining, 1);
+        assert!(admit(start).allowed);
+
+        let denied = admit(start);
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after_secs, 1);
+        assert_eq!(denied.reset_secs, 2);
+
+        assert!(admit(start + Duration::from_secs(1)).allowed);
+    }
+
+    #[test]
+    fn test_route_and_key_quotas() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let admit =
+            |headers: &HeaderMap, pattern| limiter.admit_at(headers, Some(PEER), pattern, now);
+        let anonymous = HeaderMap::new();
+
+        assert!(admit(&anonymous, "/api/v1/dex").unwrap().unwrap().allowed);
+        let denied = admit(&anonymous, "/api/v1/dex").unwrap().unwrap();
+        assert!(!denied.allowed);
+        assert_eq!(denied.retry_after_secs, 2);
+        // Other routes draw from the default bucket.
+        assert!(admit(&anonymous, "/api/v1/rates").unwrap().unwrap().allowed);
+        assert_eq!(admit(&anonymous, "/health").unwrap(), None);
+
+        let keyed = admit(&headers(&[(API_KEY_HEADER, "k1")]), "/api/v1/dex")
+            .unwrap()
+            .unwrap();
+        assert!(keyed.allowed);
+        assert_eq!(keyed.limit, 5);
+
+        let unknown = admit(&headers(&[(API_KEY_HEADER, "nope")]), "/api/v1/dex");
+        assert!(matches!(unknown, Err(ApiError::Unauthorized(_))));
+    }
+
+    #[test]
+    fn test_buckets_are_bounded() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let admit = |ip: &str, at: Instant| {
+            limiter
+                .admit_at(&HeaderMap::new(), ip.parse().ok(), "/api/v1/dex", at)
+                .unwrap()
+                .unwrap()
+        };
+
+        // Addresses within one /64 share a bucket.
+        assert!(admit("2001:db8::1", now).allowed);
+        assert!(!admit("2001:db8::ffff:2", now).allowed);
+        assert!(admit("2001:db8:0:1::1", now + Duration::from_millis(500)).allowed);
+
+        limiter
+            .buckets
+            .lock()
+            .unwrap()
+            .entries
+            .resize(NonZeroUsize::new(2).unwrap());
+        assert!(admit("1.1.1.1", now + Duration::from_secs(1)).allowed);
+        // The bucket of 2001:db8::/64 was the least recently used.
+        assert_eq!(limiter.buckets.lock().unwrap().entries.len(), 2);
+        assert!(admit("2001:db8::3", now + Duration::from_secs(1)).allowed);
+
+        // Buckets that refilled completely are dropped.
+        assert!(admit("1.1.1.1", now + Duration::from_secs(10)).allowed);
+        assert_eq!(limiter.buckets.lock().unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn test_forwarded_for() {
+        let forwarded = headers(&[(X_FORWARDED_FOR, "1.1.1.1, 2.2.2.2,3.3.3.3")]);
+
+        assert_eq!(client_ip(&forwarded, Some(PEER), 0), Some(PEER));
+        assert_eq!(client_ip(&forwarded, Some(PEER), 1), "3.3.3.3".parse().ok());
+        assert_eq!(client_ip(&forwarded, None, 2), "2.2.2.2".parse().ok());
+        assert_eq!(client_ip(&forwarded, Some(PEER), 4), Some(PEER));
+        assert_eq!(client_ip(&HeaderMap::new(), None, 1), None);
+
+        let garbage = headers(&[(X_FORWARDED_FOR, "unknown")]);
+        assert_eq!(client_ip(&garbage, Some(PEER), 1), Some(PEER));
+    }
+}
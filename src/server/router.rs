@@ -0,0 +1,231 @@
+use hyper::{body::Incoming, Method, Request};
+use std::{collections::HashMap, future::Future, pin::Pin, str::FromStr};
+
+use super::{
+    error::{ApiError, ApiResult},
+    state::AppState,
+};
+
+pub type HandlerFuture = Pin<Box<dyn Future<Output = ApiResult> + Send>>;
+
+pub type Handler = fn(Request<Incoming>, Params, AppState) -> HandlerFuture;
+
+/// Path parameters captured from `:name` segments of the matched pattern.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params(HashMap<&'static str, String>);
+
+pub struct Route {
+    pub method: Method,
+    pub pattern: &'static str,
+    segments: Vec<Segment>,
+    pub handler: Handler,
+}
+
+#[derive(Debug)]
+enum Segment {
+    Static(&'static str),
+    Param(&'static str),
+}
+
+pub enum Match<'a> {
+    Found(&'a Route, Params),
+    /// The path exists under another method; carries its pattern and the
+    /// methods it accepts.
+    MethodNotAllowed(&'static str, Vec<Method>),
+    NotFound,
+}
+
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Params {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Parses a parameter, describing which one was wrong on failure.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, ApiError> {
+        let value = self
+            .get(name)
+            .ok_or_else(|| ApiError::bad_request(format!("Missing path parameter `{}`", name)))?;
+
+        value.parse().map_err(|_| {
+            ApiError::bad_request(format!("Invalid path parameter `{}`: {}", name, value))
+        })
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    pub fn route(mut self, method: Method, pattern: &'static str, handler: Handler) -> Self {
+        let segments = split(pattern)
+            .map(|s| match s.strip_prefix(':') {
+                Some(name) => Segment::Param(name),
+                None => Segment::Static(s),
+            })
+            .collect();
+
+        self.routes.push(Route {
+            method,
+            pattern,
+            segments,
+            handler,
+        });
+
+        self
+    }
+
+    pub fn get(self, pattern: &'static str, handler: Handler) -> Self {
+        self.route(Method::GET, pattern, handler)
+    }
+
+    pub fn put(self, pattern: &'static str, handler: Handler) -> Self {
+        self.route(Method::PUT, pattern, handler)
+    }
+
+    pub fn patch(self, pattern: &'static str, handler: Handler) -> Self {
+        self.route(Method::PATCH, pattern, handler)
+    }
+
+    pub fn post(self, pattern: &'static str, handler: Handler) -> Self {
+        self.route(Method::POST, pattern, handler)
+    }
+
+    pub fn delete(self, pattern: &'static str, handler: Handler) -> Self {
+        self.route(Method::DELETE, pattern, handler)
+    }
+
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    pub fn find(&self, method: &Method, path: &str) -> Match<'_> {
+        let mut allowed = Vec::new();
+        let mut pattern = None;
+
+        for route in &self.routes {
+            let Some(params) = route.matches(path) else {
+                continue;
+            };
+
+            if route.method == method {
+                return Match::Found(route, params);
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+            pattern.get_or_insert(route.pattern);
+        }
+
+        match pattern {
+            Some(pattern) => Match::MethodNotAllowed(pattern, allowed),
+            None => Match::NotFound,
+        }
+    }
+}
+
+impl Route {
+    fn matches(&self, path: &str) -> Option<Params> {
+        let mut params = HashMap::new();
+        let mut parts = split(path);
+
+        for segment in &self.segments {
+            let part = parts.next()?;
+
+            match segment {
+                Segment::Static(s) if *s == part => {}
+                Segment::Param(name) if !part.is_empty() => {
+                    params.insert(*name, part.to_string());
+                }
+                _ => return None,
+            }
+        }
+
+        match parts.next() {
+            Some(_) => None,
+            None => Some(Params(params)),
+        }
+    }
+}
+
+fn split(path: &str) -> impl Iterator<Item = &str> {
+    path.strip_prefix('/').unwrap_or(path).split('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop(_req: Request<Incoming>, _params: Params, _state: AppState) -> HandlerFuture {
+        Box::pin(async { Err(ApiError::NotFound(String::new())) })
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/api/v1/tokens", noop)
+            .get("/api/v1/token/:id", noop)
+            .put("/api/v1/token/:id", noop)
+    }
+
+    #[test]
+    fn test_static_and_param_routes() {
+        let router = router();
+
+        match router.find(&Method::GET, "/api/v1/tokens") {
+            Match::Found(route, params) => {
+                assert_eq!(route.pattern, "/api/v1/tokens");
+                assert_eq!(params, Params::default());
+            }
+            _ => panic!("expected a match"),
+        }
+        match router.find(&Method::PUT, "/api/v1/token/0xabc") {
+            Match::Found(route, params) => {
+                assert_eq!(route.method, Method::PUT);
+                assert_eq!(params.get("id"), Some("0xabc"));
+            }
+            _ => panic!("expected a match"),
+        }
+        assert!(matches!(
+            router.find(&Method::GET, "/api/v1/token/"),
+            Match::NotFound
+        ));
+        assert!(matches!(
+            router.find(&Method::GET, "/api/v1/token/a/b"),
+            Match::NotFound
+        ));
+        assert!(matches!(
+            router.find(&Method::GET, "/api/v1/tokens/"),
+            Match::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_method_not_allowed_lists_methods() {
+        let router = router();
+
+        match router.find(&Method::DELETE, "/api/v1/token/zil") {
+            Match::MethodNotAllowed(pattern, allowed) => {
+                assert_eq!(pattern, "/api/v1/token/:id");
+                assert_eq!(allowed, vec![Method::GET, Method::PUT]);
+            }
+            _ => panic!("expected 405"),
+        }
+    }
+
+    #[test]
+    fn test_typed_params() {
+        let params = Params(HashMap::from([("n", "42".to_string())]));
+
+        assert_eq!(params.parse::<u32>("n").unwrap(), 42);
+        assert!(params.parse::<u8>("missing").is_err());
+        assert_eq!(
+            params.parse::<bool>("n").unwrap_err().to_string(),
+            "Invalid path parameter `n`: 42"
+        );
+    }
+}
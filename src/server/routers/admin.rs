@@ -0,0 +1,405 @@
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{header, Request, Response, StatusCode};
+use log::info;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use super::{query_number, query_params};
+use crate::{
+    config::settings::Scope,
+    models::{
+        audit::{AuditEntry, AuditFilter, Change},
+        revisions::{RevisionDiff, RevisionSummary},
+    },
+    server::{
+        error::{json, ApiError, ApiResult, ErrorBody},
+        state::AppState,
+    },
+};
+
+const AUDIT_DEFAULT_LIMIT: usize = 100;
+const AUDIT_MAX_LIMIT: usize = 1000;
+const REVISIONS_DEFAULT_LIMIT: usize = 50;
+const REVISIONS_MAX_LIMIT: usize = 1000;
+
+/// Query of `GET /api/v1/admin/audit`.
+#[derive(Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// Token base16 address, case-insensitive.
+    token: Option<String>,
+    /// Oldest entry to return, unix seconds.
+    from: Option<u64>,
+    /// Newest entry to return, unix seconds.
+    to: Option<u64>,
+    #[param(default = 100, maximum = 1000)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct AuditResponse {
+    count: usize,
+    /// Newest first.
+    entries: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct RefreshResponse {
+    /// `meta`, `rates` or `dex`.
+    job: &'static str,
+    /// Whether the request joined a run that was already in flight.
+    coalesced: bool,
+    duration_ms: u64,
+    /// Items held after the run; absent when it failed.
+    items: Option<usize>,
+    /// Items added, removed or modified by the run.
+    changed: Option<usize>,
+    error: Option<String>,
+}
+
+/// Query of `GET /api/v1/admin/revisions`.
+#[derive(Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RevisionsQuery {
+    #[param(default = 50, maximum = 1000)]
+    limit: Option<usize>,
+}
+
+/// Query of `GET /api/v1/admin/revisions/diff`.
+#[derive(Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffQuery {
+    /// Older revision.
+    from: u64,
+    /// Newer revision; the latest when absent.
+    to: Option<u64>,
+}
+
+impl AuditQuery {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let params = query_params(query);
+
+        Ok(AuditQuery {
+            token: params.get("token").map(|v| v.to_lowercase()),
+            from: query_number(&params, "from")?,
+            to: query_number(&params, "to")?,
+            limit: query_number(&params, "limit")?.map(|v| v as usize),
+        })
+    }
+}
+
+impl RevisionsQuery {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let params = query_params(query);
+
+        Ok(RevisionsQuery {
+            limit: query_number(&params, "limit")?.map(|v| v as usize),
+        })
+    }
+}
+
+impl DiffQuery {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let params = query_params(query);
+
+        Ok(DiffQuery {
+            from: query_number(&params, "from")?
+                .ok_or_else(|| ApiError::bad_request("Missing query parameter `from`"))?,
+            to: query_number(&params, "to")?,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit",
+    tag = "admin",
+    summary = "Audit trail of admin token edits",
+    params(AuditQuery),
+    responses(
+        (status = 200, body = AuditResponse),
+        (status = 400, description = "`bad_request`: invalid query", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `audit:read` scope", body = ErrorBody)
+    ),
+    security(("admin_key" = ["audit:read"]))
+)]
+pub async fn handle_get_audit(req: Request<hyper::body::Incoming>, state: AppState) -> ApiResult {
+    state.auth.authorize(&req, Scope::AuditRead)?;
+
+    let query = AuditQuery::parse(req.uri().query().unwrap_or(""))?;
+
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Err(ApiError::bad_request("`from` is after `to`"));
+        }
+    }
+
+    let filter = AuditFilter {
+        token: query.token,
+        from: query.from,
+        to: query.to,
+        limit: query
+            .limit
+            .unwrap_or(AUDIT_DEFAULT_LIMIT)
+            .min(AUDIT_MAX_LIMIT),
+    };
+    let entries = state
+        .audit
+        .query(&filter)
+        .map_err(|e| ApiError::Internal(format!("audit query: {}", e)))?;
+    let response = json(
+        StatusCode::OK,
+        &AuditResponse {
+            count: entries.len(),
+            entries,
+        },
+    )?;
+
+    Ok(no_store(response))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/revisions",
+    tag = "admin",
+    summary = "Revisions of the token list, newest first",
+    params(RevisionsQuery),
+    responses(
+        (status = 200, body = Vec<RevisionSummary>),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `audit:read` scope", body = ErrorBody)
+    ),
+    security(("admin_key" = ["audit:read"]))
+)]
+pub async fn handle_get_revisions(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    state.auth.authorize(&req, Scope::AuditRead)?;
+
+    let query = RevisionsQuery::parse(req.uri().query().unwrap_or(""))?;
+    let limit = query
+        .limit
+        .unwrap_or(REVISIONS_DEFAULT_LIMIT)
+        .min(REVISIONS_MAX_LIMIT);
+    let revisions = state
+        .meta
+        .read()
+        .await
+        .revisions
+        .list(limit)
+        .map_err(|e| ApiError::Internal(format!("revisions list: {}", e)))?;
+
+    json(StatusCode::OK, &revisions).map(no_store)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/revisions/diff",
+    tag = "admin",
+    summary = "Tokens added, removed and changed between two revisions",
+    params(DiffQuery),
+    responses(
+        (status = 200, body = RevisionDiff),
+        (status = 400, description = "`bad_request`: invalid query", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `audit:read` scope", body = ErrorBody),
+        (status = 404, description = "`not_found`: no such revision", body = ErrorBody)
+    ),
+    security(("admin_key" = ["audit:read"]))
+)]
+pub async fn handle_get_revision_diff(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    state.auth.authorize(&req, Scope::AuditRead)?;
+
+    let query = DiffQuery::parse(req.uri().query().unwrap_or(""))?;
+    let meta = state.meta.read().await;
+    let revision = |number: Option<u64>| {
+        let found = match number {
+            Some(number) => meta.revisions.get(number),
+            None => meta.revisions.head(),
+        };
+
+        found
+            .map_err(|e| ApiError::Internal(format!("revisions get: {}", e)))?
+            .ok_or_else(|| match number {
+                Some(number) => ApiError::NotFound(format!("No revision {}", number)),
+                None => ApiError::NotFound("No revisions yet".to_string()),
+            })
+    };
+    let diff = RevisionDiff::between(&revision(Some(query.from))?, &revision(query.to)?);
+
+    json(StatusCode::OK, &diff).map(no_store)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/revisions/{number}/rollback",
+    tag = "admin",
+    summary = "Restore a revision of the token list as a new revision",
+    params(("number" = u64, Path, description = "Revision to restore")),
+    responses(
+        (status = 200, description = "The new revision", body = RevisionSummary),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `tokens:write` scope", body = ErrorBody),
+        (status = 404, description = "`not_found`: no such revision", body = ErrorBody),
+        (status = 500, description = "`internal_error`: the rollback could not be stored", body = ErrorBody)
+    ),
+    security(("admin_key" = ["tokens:write"]))
+)]
+pub async fn handle_rollback(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+    number: u64,
+) -> ApiResult {
+    let admin = state.auth.authorize(&req, Scope::TokensWrite)?;
+    let internal = |e: std::io::Error| ApiError::Internal(format!("rollback: {}", e));
+    let mut meta = state.meta.write().await;
+    let target = meta
+        .revisions
+        .get(number)
+        .map_err(internal)?
+        .ok_or_else(|| ApiError::NotFound(format!("No revision {}", number)))?;
+
+    let changes = match meta.revisions.head().map_err(internal)? {
+        Some(head) => audit_changes(&RevisionDiff::between(&head, &target)),
+        None => Vec::new(),
+    };
+    let revision = meta
+        .rollback(number, &admin.id, || {
+            state.audit.append_all(&admin.id, changes).map(drop)
+        })
+        .map_err(internal)?
+        .ok_or_else(|| ApiError::NotFound(format!("No revision {}", number)))?;
+
+    state
+        .snapshots
+        .publish_tokens(meta.list.clone())
+        .map_err(|e| ApiError::Internal(format!("tokens snapshot: {}", e)))?;
+
+    info!(
+        "admin: `{}` rolled back tokens to revision {} as {}",
+        admin.id, number, revision.number
+    );
+
+    json(
+        StatusCode::OK,
+        &RevisionSummary {
+            number: revision.number,
+            timestamp: revision.timestamp,
+            author: revision.author,
+            rollback_of: revision.rollback_of,
+            count: revision.tokens.len(),
+        },
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/refresh/{job}",
+    tag = "admin",
+    summary = "Run an updater now",
+    description = "Joins the run in flight, if any, instead of starting another. A failed run is still a 200, with `error` set.",
+    params(("job" = String, Path, description = "`meta`, `rates` or `dex`")),
+    responses(
+        (status = 200, description = "Outcome of the run", body = RefreshResponse),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `admin:refresh` scope", body = ErrorBody),
+        (status = 404, description = "`not_found`: no such updater", body = ErrorBody)
+    ),
+    security(("admin_key" = ["admin:refresh"]))
+)]
+pub async fn handle_refresh(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+    job: String,
+) -> ApiResult {
+    let admin = state.auth.authorize(&req, Scope::AdminRefresh)?;
+    let handle = state
+        .scheduler
+        .job(&job)
+        .cloned()
+        .ok_or_else(|| ApiError::NotFound(format!("No updater {}", job)))?;
+
+    info!("admin: `{}` triggered {}", admin.id, handle.name());
+
+    // The run is its own task, so a client hanging up cannot cancel it
+    // halfway through an update.
+    let run = tokio::task::spawn({
+        let handle = Arc::clone(&handle);
+
+        async move { handle.trigger().await }
+    })
+    .await
+    .map_err(|e| ApiError::Internal(format!("refresh {}: {}", handle.name(), e)))?;
+    let (outcome, error) = match run.result {
+        Ok(outcome) => (Some(outcome), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+
+    json(
+        StatusCode::OK,
+        &RefreshResponse {
+            job: handle.name(),
+            coalesced: run.coalesced,
+            duration_ms: run.duration.as_millis() as u64,
+            items: outcome.as_ref().map(|o| o.items),
+            changed: outcome.as_ref().map(|o| o.changed),
+            error,
+        },
+    )
+    .map(no_store)
+}
+
+/// Audit entries per token for a rollback: changed fields, and whole
+/// tokens as the `token` field when added or removed.
+fn audit_changes(diff: &RevisionDiff) -> Vec<(String, Vec<Change>)> {
+    let whole = |token, old_value, new_value| {
+        (
+            token,
+            vec![Change {
+                field: "token".to_string(),
+                old_value,
+                new_value,
+            }],
+        )
+    };
+    let changed = diff.changed.iter().map(|token| {
+        let changes = token
+            .fields
+            .iter()
+            .map(|(field, change)| Change {
+                field: field.clone(),
+                old_value: change.from.clone(),
+                new_value: change.to.clone(),
+            })
+            .collect();
+
+        (token.base16.clone(), changes)
+    });
+    let added = diff
+        .added
+        .iter()
+        .map(|t| whole(t.base16.to_lowercase(), Value::Null, json!(t)));
+    let removed = diff
+        .removed
+        .iter()
+        .map(|t| whole(t.base16.to_lowercase(), json!(t), Value::Null));
+
+    changed.chain(added).chain(removed).collect()
+}
+
+fn no_store(mut response: Response<Full<Bytes>>) -> Response<Full<Bytes>> {
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+
+    response
+}
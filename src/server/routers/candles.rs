@@ -0,0 +1,138 @@
+use hyper::{Request, StatusCode};
+use serde::Serialize;
+use utoipa::{IntoParams, ToSchema};
+
+use super::{query_number, query_params};
+use crate::{
+    models::candles::{Candle, Resolution, ZIL},
+    server::{
+        cache::CachePolicy,
+        error::{json, ApiError, ApiResult, ErrorBody},
+        state::AppState,
+    },
+};
+
+const CANDLES_DEFAULT_LIMIT: usize = 300;
+const CANDLES_MAX_LIMIT: usize = 1000;
+
+/// Query of `GET /api/v1/candles/{asset}`.
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CandlesQuery {
+    /// Lowercase currency code from `candles.currencies`, or `zil` for tokens.
+    #[param(default = "usd")]
+    currency: String,
+    #[param(value_type = Option<Resolution>, default = "1h")]
+    resolution: Resolution,
+    /// Newest candles to return.
+    #[param(default = 300, maximum = 1000)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct CandlesResponse {
+    /// `zil`, or the lowercase base16 address of the token.
+    asset: String,
+    currency: String,
+    resolution: Resolution,
+    /// Oldest first; the last one is still open.
+    candles: Vec<Candle>,
+}
+
+impl CandlesQuery {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let params = query_params(query);
+        let resolution = match params.get("resolution") {
+            Some(v) => Resolution::parse(v).ok_or_else(|| {
+                ApiError::bad_request(format!("Invalid query parameter `resolution`: {}", v))
+            })?,
+            None => Resolution::Hour,
+        };
+
+        Ok(CandlesQuery {
+            currency: params
+                .get("currency")
+                .map_or_else(|| "usd".to_string(), |v| v.to_lowercase()),
+            resolution,
+            limit: query_number(&params, "limit")?.map(|v| v as usize),
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/candles/{asset}",
+    tag = "rates",
+    summary = "OHLC candles of ZIL or a listed token",
+    description = "Token prices come from the dex pool reserves. Candles are updated on every rates and dex update; 5 minute candles are kept for 7 days and hourly ones for 90 days.",
+    params(
+        ("asset" = String, Path, description = "`zil`, or a token symbol, base16 or bech32 address"),
+        CandlesQuery
+    ),
+    responses(
+        (status = 200, body = CandlesResponse),
+        (status = 400, description = "`bad_request`: invalid query or a currency without candles", body = ErrorBody),
+        (status = 404, description = "`not_found`: unknown asset", body = ErrorBody)
+    )
+)]
+pub async fn handle_get_candles(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+    asset: String,
+) -> ApiResult {
+    let query = CandlesQuery::parse(req.uri().query().unwrap_or(""))?;
+    let asset = resolve_asset(&state, &asset)
+        .ok_or_else(|| ApiError::NotFound(format!("No asset {}", asset)))?;
+
+    if !state.series.candles.quotes(&asset, &query.currency) {
+        return Err(ApiError::bad_request(format!(
+            "No candles of {} in {}",
+            asset, query.currency
+        )));
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(CANDLES_DEFAULT_LIMIT)
+        .min(CANDLES_MAX_LIMIT);
+    let candles = state
+        .series
+        .candles
+        .series(&asset, &query.currency, query.resolution, limit)
+        .map_err(|e| ApiError::Internal(format!("candles: {}", e)))?;
+    let policy = CachePolicy::dataset(&state, &["dex", "rates"]);
+    let response = json(
+        StatusCode::OK,
+        &CandlesResponse {
+            asset,
+            currency: query.currency,
+            resolution: query.resolution,
+            candles,
+        },
+    )?;
+
+    Ok(policy.apply(response))
+}
+
+/// The candle name of `id`: `zil`, or the base16 of the token it names.
+fn resolve_asset(state: &AppState, id: &str) -> Option<String> {
+    let id = id.to_lowercase();
+
+    if id == ZIL {
+        return Some(id);
+    }
+
+    let base16 = format!("0x{}", id.trim_start_matches("0x"));
+
+    state
+        .snapshots
+        .load()
+        .tokens
+        .iter()
+        .find(|t| {
+            t.base16.eq_ignore_ascii_case(&base16)
+                || t.bech32.eq_ignore_ascii_case(&id)
+                || t.symbol.eq_ignore_ascii_case(&id)
+        })
+        .map(|t| t.base16.to_lowercase())
+}
@@ -0,0 +1,157 @@
+use hyper::{header, Request, StatusCode};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+use crate::{
+    scheduler::JobStatus,
+    server::{
+        error::{self, ApiResult},
+        state::AppState,
+    },
+    utils::time::now_secs,
+};
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DatasetHealth {
+    ready: bool,
+    items: usize,
+    last_update: Option<u64>,
+    age_secs: Option<u64>,
+    max_age_secs: u64,
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    last_error_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct HealthResponse {
+    /// `ok`, `ready` or `not_ready`.
+    status: &'static str,
+    uptime_secs: u64,
+    /// Keyed by `meta`, `rates` and `dex`.
+    #[schema(value_type = BTreeMap<String, DatasetHealth>)]
+    datasets: BTreeMap<&'static str, DatasetHealth>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    summary = "Liveness probe with per-dataset freshness",
+    responses((status = 200, body = HealthResponse))
+)]
+pub async fn handle_health(_req: Request<hyper::body::Incoming>, state: AppState) -> ApiResult {
+    let res = HealthResponse {
+        status: "ok",
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        datasets: datasets(&state),
+    };
+
+    json_response(StatusCode::OK, &res)
+}
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    summary = "Readiness probe",
+    responses(
+        (status = 200, description = "Every dataset is fresh", body = HealthResponse),
+        (status = 503, description = "A dataset is empty or stale", body = HealthResponse)
+    )
+)]
+pub async fn handle_ready(_req: Request<hyper::body::Incoming>, state: AppState) -> ApiResult {
+    let datasets = datasets(&state);
+    let ready = datasets.values().all(|d| d.ready);
+    let (status, code) = if ready {
+        ("ready", StatusCode::OK)
+    } else {
+        ("not_ready", StatusCode::SERVICE_UNAVAILABLE)
+    };
+    let res = HealthResponse {
+        status,
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        datasets,
+    };
+
+    json_response(code, &res)
+}
+
+fn datasets(state: &AppState) -> BTreeMap<&'static str, DatasetHealth> {
+    let health = &state.settings.health;
+    let now = now_secs();
+    let snapshot = state.snapshots.load();
+    let counts = [
+        ("meta", snapshot.tokens.len(), health.meta_max_age_secs),
+        (
+            "rates",
+            snapshot.rates.prices.len(),
+            health.rates_max_age_secs,
+        ),
+        ("dex", snapshot.pools.len(), health.dex_max_age_secs),
+    ];
+
+    counts
+        .into_iter()
+        .map(|(name, items, max_age_secs)| {
+            let status = state.scheduler.job(name).map(|j| j.status());
+            let dataset = dataset_health(items, status.unwrap_or_default(), max_age_secs, now);
+
+            (name, dataset)
+        })
+        .collect()
+}
+
+fn dataset_health(items: usize, status: JobStatus, max_age_secs: u64, now: u64) -> DatasetHealth {
+    let age_secs = status.last_success.map(|t| now.saturating_sub(t));
+
+    DatasetHealth {
+        ready: age_secs.is_some_and(|age| age <= max_age_secs),
+        items,
+        last_update: status.last_success,
+        age_secs,
+        max_age_secs,
+        consecutive_failures: status.consecutive_failures,
+        last_error: status.last_error,
+        last_error_at: status.last_error_at,
+    }
+}
+
+fn json_response(status: StatusCode, res: &HealthResponse) -> ApiResult {
+    let mut response = error::json(status, res)?;
+
+    response.headers_mut().insert(
+        header::CACHE_CONTROL,
+        header::HeaderValue::from_static("no-store"),
+    );
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dataset_freshness() {
+        let never = dataset_health(10, JobStatus::default(), 60, 1_000);
+        assert!(!never.ready);
+        assert_eq!(never.age_secs, None);
+
+        let status = JobStatus {
+            last_success: Some(950),
+            last_error: Some("upstream error: timeout".to_string()),
+            ..Default::default()
+        };
+
+        let fresh = dataset_health(10, status.clone(), 60, 1_000);
+        assert!(fresh.ready);
+        assert_eq!(fresh.age_secs, Some(50));
+        assert_eq!(fresh.last_error.as_deref(), Some("upstream error: timeout"));
+
+        let stale = dataset_health(10, status, 60, 1_100);
+        assert!(!stale.ready);
+        assert_eq!(stale.age_secs, Some(150));
+    }
+}
@@ -0,0 +1,36 @@
+use hyper::{Request, StatusCode};
+
+use crate::{
+    metrics,
+    server::{
+        error::{bytes_response, ApiError, ApiResult},
+        state::AppState,
+    },
+};
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    summary = "Prometheus metrics",
+    responses((status = 200, description = "Prometheus text format 0.0.4", content_type = "text/plain", body = String))
+)]
+pub async fn handle_get_metrics(
+    _req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    let snapshot = state.snapshots.load();
+
+    metrics::set_dataset_items("tokens", snapshot.tokens.len());
+    metrics::set_dataset_items("pools", snapshot.pools.len());
+    metrics::set_dataset_items("currencies", snapshot.rates.prices.len());
+
+    let text = metrics::gather()
+        .map_err(|e| ApiError::Internal(format!("metrics: cannot encode: {}", e)))?;
+
+    Ok(bytes_response(
+        StatusCode::OK,
+        "text/plain; version=0.0.4; charset=utf-8",
+        text,
+    ))
+}
@@ -1,38 +1,326 @@
 use bytes::Bytes;
 use http_body_util::Full;
-use hyper::StatusCode;
-use hyper::{Request, Response};
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use hyper::{
+    header::{self, HeaderValue},
+    Method, Request, Response, StatusCode,
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
 
-use crate::models::{currencies::Currencies, dex::Dex, meta::Meta};
+use super::{
+    cache::{self, Conditions},
+    compression::Encoding,
+    error::{allow_header, ApiError},
+    rate_limit::ClientAddr,
+    router::{Match, Router},
+    state::AppState,
+};
 
+mod admin;
+mod candles;
 mod dex;
+mod health;
+mod metrics;
+mod openapi;
 mod rates;
 mod stake;
 mod tokens;
 
+/// Headers browsers may send on cross-origin calls, e.g. the admin `PUT`.
+const CORS_ALLOW_HEADERS: &str = "Authorization, Content-Type, X-API-Key";
+/// Response headers scripts may read besides the CORS-safelisted ones.
+const CORS_EXPOSE_HEADERS: &str =
+    "ETag, Retry-After, X-RateLimit-Limit, X-RateLimit-Remaining, X-RateLimit-Reset";
+const CORS_MAX_AGE_SECS: &str = "86400";
+
+static ROUTER: LazyLock<Router> = LazyLock::new(|| {
+    Router::new()
+        .get("/health", |req, _, state| {
+            Box::pin(health::handle_health(req, state))
+        })
+        .get("/ready", |req, _, state| {
+            Box::pin(health::handle_ready(req, state))
+        })
+        .get("/metrics", |req, _, state| {
+            Box::pin(metrics::handle_get_metrics(req, state))
+        })
+        .get("/api/openapi.json", |req, _, _| {
+            Box::pin(openapi::handle_get_openapi(req))
+        })
+        .get("/api/v1/dex", |req, _, state| {
+            Box::pin(dex::handle_get_pools(req, state))
+        })
+        .get("/api/v1/rates", |req, _, state| {
+            Box::pin(rates::handle_get_rates(req, state))
+        })
+        .get("/api/v1/rates/details", |req, _, state| {
+            Box::pin(rates::handle_get_rates_details(req, state))
+        })
+        .get("/api/v1/rates/history", |req, _, state| {
+            Box::pin(rates::handle_get_rates_history(req, state))
+        })
+        .get("/api/v1/candles/:asset", |req, params, state| {
+            Box::pin(async move {
+                let asset = params.parse("asset")?;
+
+                candles::handle_get_candles(req, state, asset).await
+            })
+        })
+        .get("/api/v1/stake/pools", |req, _, _| {
+            Box::pin(stake::handle_get_pools(req))
+        })
+        .get("/api/v2/stake/pools", |req, _, _| {
+            Box::pin(stake::handle_get_poolsv2(req))
+        })
+        .get("/api/v1/tokens", |req, _, state| {
+            Box::pin(tokens::handle_get_tokens(req, state))
+        })
+        .get("/api/v1/token/:id", |req, params, state| {
+            Box::pin(async move {
+                let symbol = params.parse::<String>("id")?.to_lowercase();
+
+                tokens::handle_get_token(req, state, symbol).await
+            })
+        })
+        .put("/api/v1/token/:id", |req, params, state| {
+            Box::pin(async move {
+                let tokens::Base16(base16) = params.parse("id")?;
+
+                tokens::handle_put_token(req, state, base16).await
+            })
+        })
+        .patch("/api/v1/token/:id", |req, params, state| {
+            Box::pin(async move {
+                let tokens::Base16(base16) = params.parse("id")?;
+
+                tokens::handle_update_token(req, state, base16).await
+            })
+        })
+        .post("/api/v1/tokens/bulk", |req, _, state| {
+            Box::pin(tokens::handle_bulk_update_tokens(req, state))
+        })
+        .delete("/api/v1/token/:id", |req, params, state| {
+            Box::pin(async move {
+                let tokens::Base16(base16) = params.parse("id")?;
+
+                tokens::handle_delete_token(req, state, base16).await
+            })
+        })
+        .post("/api/v1/token", |req, _, state| {
+            Box::pin(tokens::handle_create_token(req, state))
+        })
+        .get("/api/v1/admin/audit", |req, _, state| {
+            Box::pin(admin::handle_get_audit(req, state))
+        })
+        .post("/api/v1/admin/refresh/:job", |req, params, state| {
+            Box::pin(async move {
+                let job = params.parse("job")?;
+
+                admin::handle_refresh(req, state, job).await
+            })
+        })
+        .get("/api/v1/admin/revisions", |req, _, state| {
+            Box::pin(admin::handle_get_revisions(req, state))
+        })
+        .get("/api/v1/admin/revisions/diff", |req, _, state| {
+            Box::pin(admin::handle_get_revision_diff(req, state))
+        })
+        .post(
+            "/api/v1/admin/revisions/:number/rollback",
+            |req, params, state| {
+                Box::pin(async move {
+                    let number = params.parse("number")?;
+
+                    admin::handle_rollback(req, state, number).await
+                })
+            },
+        )
+});
+
 pub async fn route(
-    req: Request<hyper::body::Incoming>,
-    meta: Arc<RwLock<Meta>>,
-    dex: Arc<RwLock<Dex>>,
-    rates: Arc<RwLock<Currencies>>,
+    mut req: Request<hyper::body::Incoming>,
+    peer: Option<IpAddr>,
+    state: AppState,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    match (req.method(), req.uri().path()) {
-        (&hyper::Method::GET, "/api/v1/dex") => dex::handle_get_pools(req, meta, dex, rates).await,
-        (&hyper::Method::GET, "/api/v1/rates") => rates::handle_get_rates(req, rates).await,
-        (&hyper::Method::GET, "/api/v1/stake/pools") => stake::handle_get_pools(req).await,
-        (&hyper::Method::GET, "/api/v2/stake/pools") => stake::handle_get_poolsv2(req).await,
-        (&hyper::Method::GET, "/api/v1/tokens") => tokens::handle_get_tokens(req, meta).await,
-        (&hyper::Method::GET, path) if path.starts_with("/api/v1/token/") => {
-            tokens::handle_get_token(req, meta).await
+    let started = Instant::now();
+
+    if let Some(ip) = state.limiter.client(req.headers(), peer) {
+        req.extensions_mut().insert(ClientAddr(ip));
+    }
+
+    let method = req.method().clone();
+    let conditions = Conditions::from_headers(req.headers());
+    let encoding = Encoding::negotiate(req.headers());
+    let compressor = Arc::clone(&state.compressor);
+    let found = ROUTER.find(&method, req.uri().path());
+    // The matched pattern doubles as the metrics label and rate limit
+    // scope, keeping path parameters out of both.
+    let label = match &found {
+        Match::Found(route, _) => route.pattern,
+        Match::MethodNotAllowed(pattern, _) => pattern,
+        Match::NotFound => "unmatched",
+    };
+    // Preflights are sent by browsers on their own and are not counted.
+    let admission = match method {
+        Method::OPTIONS => Ok(None),
+        _ => state.limiter.admit(req.headers(), peer, label),
+    };
+    let decision = admission.as_ref().ok().copied().flatten();
+    let result = match (admission, found) {
+        (Err(e), _) => Err(e),
+        (Ok(Some(decision)), _) if !decision.allowed => {
+            Err(ApiError::TooManyRequests(decision.retry_after_secs))
+        }
+        (Ok(_), Match::Found(route, params)) => (route.handler)(req, params, state).await,
+        (Ok(_), Match::MethodNotAllowed(_, allowed)) if method == Method::OPTIONS => {
+            Ok(preflight(&allowed))
         }
-        (&hyper::Method::PUT, path) if path.starts_with("/api/v1/token/") => {
-            tokens::handle_update_token(req, meta).await
+        (Ok(_), Match::MethodNotAllowed(_, allowed)) => Err(ApiError::MethodNotAllowed(allowed)),
+        (Ok(_), Match::NotFound) => Err(ApiError::NotFound(format!(
+            "No route for {}",
+            req.uri().path()
+        ))),
+    };
+    let mut response = result.unwrap_or_else(ApiError::into_response);
+
+    // Only responses that opted into caching via `CachePolicy` get validators.
+    if method == Method::GET
+        && response
+            .headers()
+            .get(header::CACHE_CONTROL)
+            .is_some_and(|v| v.as_bytes().starts_with(b"public"))
+    {
+        response = cache::revalidate(&conditions, response).await;
+    }
+
+    response = compressor.encode(response, encoding).await;
+
+    response.headers_mut().insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    response.headers_mut().insert(
+        header::ACCESS_CONTROL_EXPOSE_HEADERS,
+        HeaderValue::from_static(CORS_EXPOSE_HEADERS),
+    );
+    if let Some(decision) = decision {
+        decision.apply(response.headers_mut());
+    }
+
+    crate::metrics::observe_request(
+        label,
+        method.as_str(),
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+
+    Ok(response)
+}
+
+fn preflight(allowed: &[Method]) -> Response<Full<Bytes>> {
+    let allow = allow_header(allowed);
+    let mut response = Response::new(Full::new(Bytes::new()));
+
+    *response.status_mut() = StatusCode::NO_CONTENT;
+
+    let headers = response.headers_mut();
+
+    headers.insert(header::ALLOW, allow.clone());
+    headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, allow);
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static(CORS_ALLOW_HEADERS),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_MAX_AGE,
+        HeaderValue::from_static(CORS_MAX_AGE_SECS),
+    );
+
+    response
+}
+
+/// Decoded query string; a repeated name keeps its last value.
+fn query_params(query: &str) -> HashMap<String, String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect()
+}
+
+fn query_number(params: &HashMap<String, String>, name: &str) -> Result<Option<u64>, ApiError> {
+    params
+        .get(name)
+        .map(|v| {
+            v.parse::<u64>().map_err(|_| {
+                ApiError::bad_request(format!("Invalid query parameter `{}`: {}", name, v))
+            })
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utoipa::OpenApi;
+
+    #[test]
+    fn test_routes_are_registered() {
+        assert!(matches!(
+            ROUTER.find(&Method::GET, "/api/v1/token/zil"),
+            Match::Found(..)
+        ));
+        assert!(matches!(
+            ROUTER.find(&Method::POST, "/api/v1/rates"),
+            Match::MethodNotAllowed("/api/v1/rates", _)
+        ));
+        assert!(matches!(
+            ROUTER.find(&Method::GET, "/api/v1/nothing"),
+            Match::NotFound
+        ));
+    }
+
+    #[test]
+    fn test_openapi_covers_every_route() {
+        let spec = serde_json::to_value(openapi::ApiDoc::openapi()).unwrap();
+
+        for route in ROUTER.routes() {
+            let path = route
+                .pattern
+                .split('/')
+                .map(|s| match s.strip_prefix(':') {
+                    Some(name) => format!("{{{}}}", name),
+                    None => s.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("/");
+            let method = route.method.as_str().to_lowercase();
+
+            assert!(
+                spec["paths"][&path][&method].is_object(),
+                "{} {} is missing from the OpenAPI document",
+                route.method,
+                path
+            );
         }
-        _ => Ok(Response::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body(Full::new(Bytes::from("Not Found")))
-            .unwrap()),
+    }
+
+    #[test]
+    fn test_preflight_headers() {
+        let response = preflight(&[Method::GET, Method::PUT]);
+        let headers = response.headers();
+
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(headers[header::ALLOW], "GET, PUT, OPTIONS");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, PUT, OPTIONS"
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            CORS_ALLOW_HEADERS
+        );
     }
 }
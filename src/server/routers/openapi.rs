@@ -0,0 +1,83 @@
+use hyper::{Request, StatusCode};
+use std::sync::LazyLock;
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::server::{
+    cache::{CachePolicy, STATIC_MAX_AGE_SECS},
+    error::{bytes_response, ApiError, ApiResult},
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "data-rs", description = "Zilliqa token metadata, dex pools and ZIL rates"),
+    paths(
+        super::health::handle_health,
+        super::health::handle_ready,
+        super::metrics::handle_get_metrics,
+        handle_get_openapi,
+        super::dex::handle_get_pools,
+        super::rates::handle_get_rates,
+        super::rates::handle_get_rates_details,
+        super::rates::handle_get_rates_history,
+        super::candles::handle_get_candles,
+        super::stake::handle_get_pools,
+        super::stake::handle_get_poolsv2,
+        super::tokens::handle_get_tokens,
+        super::tokens::handle_get_token,
+        super::tokens::handle_update_token,
+        super::tokens::handle_put_token,
+        super::tokens::handle_bulk_update_tokens,
+        super::tokens::handle_create_token,
+        super::tokens::handle_delete_token,
+        super::admin::handle_get_audit,
+        super::admin::handle_get_revisions,
+        super::admin::handle_get_revision_diff,
+        super::admin::handle_rollback,
+        super::admin::handle_refresh,
+    ),
+    modifiers(&AdminKey)
+)]
+pub struct ApiDoc;
+
+struct AdminKey;
+
+impl Modify for AdminKey {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            "admin_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "Authorization",
+                "An admin key holding the listed scope, sent as `Bearer <key>` or as is",
+            ))),
+        );
+    }
+}
+
+/// The document never changes at runtime, so it is rendered once.
+static SPEC: LazyLock<Result<String, String>> =
+    LazyLock::new(|| ApiDoc::openapi().to_json().map_err(|e| e.to_string()));
+
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "meta",
+    summary = "This OpenAPI document",
+    responses((status = 200, description = "OpenAPI 3.1 document", content_type = "application/json"))
+)]
+pub async fn handle_get_openapi(_req: Request<hyper::body::Incoming>) -> ApiResult {
+    match &*SPEC {
+        Ok(json) => Ok(
+            CachePolicy::fixed(STATIC_MAX_AGE_SECS).apply(bytes_response(
+                StatusCode::OK,
+                "application/json",
+                json.clone(),
+            )),
+        ),
+        Err(e) => Err(ApiError::Internal(format!("openapi: {}", e))),
+    }
+}
@@ -1,33 +1,202 @@
-use bytes::Bytes;
-use http_body_util::Full;
-use hyper::{
-    header::{self, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN},
-    http::HeaderValue,
-    Request, Response,
+use hyper::{Request, StatusCode};
+use serde::Serialize;
+use utoipa::{IntoParams, ToSchema};
+
+use super::{query_number, query_params};
+use crate::{
+    models::{
+        currencies::{Prices, RatesDetails},
+        history::{HistoryPoint, Interval},
+    },
+    server::{
+        cache::CachePolicy,
+        error::{json, ApiError, ApiResult, ErrorBody},
+        state::AppState,
+    },
+    utils::time::now_secs,
 };
-use std::sync::Arc;
-use tokio::sync::RwLock;
 
-use crate::models::currencies::Currencies;
+/// Range of `GET /api/v1/rates/history` when `from` is absent.
+const HISTORY_DEFAULT_RANGE_SECS: u64 = 24 * 3600;
 
-pub async fn handle_get_rates(
-    _req: Request<hyper::body::Incoming>,
-    rates: Arc<RwLock<Currencies>>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
+/// Query of `GET /api/v1/rates`.
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RatesQuery {
+    /// `1` or `true` adds `sources`, `changes` and `stale` next to the prices.
+    #[param(default = false)]
+    details: bool,
+}
+
+/// Query of `GET /api/v1/rates/history`.
+#[derive(Debug, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// Currency code, case-insensitive.
+    currency: String,
+    /// Oldest point to return, unix seconds; 24 hours before `to` when absent.
+    from: Option<u64>,
+    /// Newest point to return, unix seconds; now when absent.
+    to: Option<u64>,
+    /// `raw`, `1h` or `1d`; the finest one still holding points at `from`
+    /// when absent.
+    #[param(value_type = Option<Interval>)]
+    interval: Option<Interval>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct HistoryResponse {
+    /// Lowercase currency code.
+    currency: String,
+    interval: Interval,
+    from: u64,
+    to: u64,
+    /// Oldest first.
+    points: Vec<HistoryPoint>,
+}
+
+impl RatesQuery {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let details = match query_params(query).get("details").map(String::as_str) {
+            None | Some("0" | "false") => false,
+            Some("1" | "true") => true,
+            Some(v) => {
+                return Err(ApiError::bad_request(format!(
+                    "Invalid query parameter `details`: {}",
+                    v
+                )))
+            }
+        };
+
+        Ok(RatesQuery { details })
+    }
+}
+
+impl HistoryQuery {
+    fn parse(query: &str) -> Result<Self, ApiError> {
+        let params = query_params(query);
+        let interval = params
+            .get("interval")
+            .map(|v| {
+                Interval::parse(v).ok_or_else(|| {
+                    ApiError::bad_request(format!("Invalid query parameter `interval`: {}", v))
+                })
+            })
+            .transpose()?;
+
+        Ok(HistoryQuery {
+            currency: params
+                .get("currency")
+                .map(|v| v.to_lowercase())
+                .ok_or_else(|| ApiError::bad_request("Missing query parameter `currency`"))?,
+            from: query_number(&params, "from")?,
+            to: query_number(&params, "to")?,
+            interval,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/rates",
+    tag = "rates",
+    summary = "ZIL price per currency",
+    description = "Each price is the weighted median of the sources quoting it. With `details=1`, the prices come with `sources`, `changes` and `stale` as in `/api/v1/rates/details`.",
+    params(RatesQuery),
+    responses(
+        (status = 200, description = "Lowercase currency code to ZIL price; with `details=1`, also `sources`, `changes` and `stale`", body = Prices),
+        (status = 400, description = "`bad_request`: invalid query", body = ErrorBody)
+    )
+)]
+pub async fn handle_get_rates(req: Request<hyper::body::Incoming>, state: AppState) -> ApiResult {
     // TODO: add currency query.
-    let json = rates.read().await.serializatio();
-    let mut response = Response::builder()
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Full::new(Bytes::from(json)))
-        .unwrap();
-
-    response
-        .headers_mut()
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    response.headers_mut().insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET"),
-    );
-
-    Ok(response)
+    let query = RatesQuery::parse(req.uri().query().unwrap_or(""))?;
+    let policy = CachePolicy::dataset(&state, &["rates"]);
+    let snapshot = state.snapshots.load();
+    let rendered = if query.details {
+        &snapshot.rates_with_details_body
+    } else {
+        &snapshot.rates_body
+    };
+
+    Ok(policy.apply(rendered.response()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/rates/details",
+    tag = "rates",
+    summary = "Sources and moves of the ZIL prices",
+    description = "`sources` lists the ones that contributed to `/api/v1/rates`, with their weight. `changes` holds the 1h, 24h and 7d moves of each price from the stored history. `stale` lists the currencies no source quotes now, whose last price is served for up to 5 refresh intervals.",
+    responses((status = 200, body = RatesDetails))
+)]
+pub async fn handle_get_rates_details(
+    _req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    let policy = CachePolicy::dataset(&state, &["rates"]);
+
+    Ok(policy.apply(state.snapshots.load().rates_details_body.response()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/rates/history",
+    tag = "rates",
+    summary = "ZIL price history in one currency",
+    description = "Every accepted snapshot is kept for 24 hours, one per hour for 90 days and one per day forever. Hourly and daily points are stamped with the start of their bucket and hold the last price within it.",
+    params(HistoryQuery),
+    responses(
+        (status = 200, body = HistoryResponse),
+        (status = 400, description = "`bad_request`: invalid query or unknown currency", body = ErrorBody)
+    )
+)]
+pub async fn handle_get_rates_history(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    let query = HistoryQuery::parse(req.uri().query().unwrap_or(""))?;
+    let now = now_secs();
+    let to = query.to.unwrap_or(now);
+    let from = query
+        .from
+        .unwrap_or_else(|| to.saturating_sub(HISTORY_DEFAULT_RANGE_SECS));
+
+    if from > to {
+        return Err(ApiError::bad_request("`from` is after `to`"));
+    }
+    if !state
+        .settings
+        .rates
+        .currencies
+        .iter()
+        .any(|c| c.eq_ignore_ascii_case(&query.currency))
+    {
+        return Err(ApiError::bad_request(format!(
+            "Unknown currency: {}",
+            query.currency
+        )));
+    }
+
+    let interval = query
+        .interval
+        .unwrap_or_else(|| Interval::covering(from, now));
+    let points = state
+        .series
+        .history
+        .series(&query.currency, from, to, interval)
+        .map_err(|e| ApiError::Internal(format!("rates history: {}", e)))?;
+    let policy = CachePolicy::dataset(&state, &["rates"]);
+    let response = json(
+        StatusCode::OK,
+        &HistoryResponse {
+            currency: query.currency,
+            interval,
+            from,
+            to,
+            points,
+        },
+    )?;
+
+    Ok(policy.apply(response))
 }
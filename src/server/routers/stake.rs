@@ -1,19 +1,20 @@
-use bytes::Bytes;
-use http_body_util::Full;
-use hyper::{
-    header::{self, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN},
-    http::HeaderValue,
-    Request, Response,
-};
+use hyper::{Request, StatusCode};
 use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::server::{
+    cache::{CachePolicy, STATIC_MAX_AGE_SECS},
+    error::{json, ApiResult},
+};
 
-#[derive(Debug, Serialize)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Serialize, ToSchema)]
 enum StakingPoolType {
     LIQUID,
     NORMAL,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct EvmPool<'a> {
     address: &'a str,
     token_address: &'a str,
@@ -23,7 +24,8 @@ struct EvmPool<'a> {
     token_symbol: &'a str,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = StakeToken)]
 struct Token<'a> {
     pub name: &'a str,
     pub symbol: &'a str,
@@ -31,7 +33,7 @@ struct Token<'a> {
     pub address: &'a str,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 struct EvmPoolV2<'a> {
     address: &'a str,
     token: Option<Token<'a>>,
@@ -129,28 +131,15 @@ const MAINNET_POOLS: [EvmPool; 11] = [
     },
 ];
 
-pub async fn handle_get_pools(
-    _req: Request<hyper::body::Incoming>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let json = serde_json::to_string(&MAINNET_POOLS).unwrap_or_else(|e| {
-        eprintln!("Error serializing pools: {}", e);
-        "[]".to_string()
-    });
-
-    let mut response = Response::builder()
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Full::new(Bytes::from(json)))
-        .unwrap();
-
-    response
-        .headers_mut()
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    response.headers_mut().insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET"),
-    );
-
-    Ok(response)
+#[utoipa::path(
+    get,
+    path = "/api/v1/stake/pools",
+    tag = "stake",
+    summary = "Mainnet staking pools",
+    responses((status = 200, body = [EvmPool]))
+)]
+pub async fn handle_get_pools(_req: Request<hyper::body::Incoming>) -> ApiResult {
+    json(StatusCode::OK, &MAINNET_POOLS).map(|r| CachePolicy::fixed(STATIC_MAX_AGE_SECS).apply(r))
 }
 
 const MAINNET_POOLS_V2: [EvmPoolV2; 12] = [
@@ -241,26 +230,14 @@ const MAINNET_POOLS_V2: [EvmPoolV2; 12] = [
     },
 ];
 
-pub async fn handle_get_poolsv2(
-    _req: Request<hyper::body::Incoming>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let json = serde_json::to_string(&MAINNET_POOLS_V2).unwrap_or_else(|e| {
-        eprintln!("Error serializing pools: {}", e);
-        "[]".to_string()
-    });
-
-    let mut response = Response::builder()
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Full::new(Bytes::from(json)))
-        .unwrap();
-
-    response
-        .headers_mut()
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    response.headers_mut().insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET"),
-    );
-
-    Ok(response)
+#[utoipa::path(
+    get,
+    path = "/api/v2/stake/pools",
+    tag = "stake",
+    summary = "Mainnet staking pools with their liquid token",
+    responses((status = 200, body = [EvmPoolV2]))
+)]
+pub async fn handle_get_poolsv2(_req: Request<hyper::body::Incoming>) -> ApiResult {
+    json(StatusCode::OK, &MAINNET_POOLS_V2)
+        .map(|r| CachePolicy::fixed(STATIC_MAX_AGE_SECS).apply(r))
 }
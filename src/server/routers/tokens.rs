@@ -1,250 +1,835 @@
-use crate::models::meta::Meta;
-use crate::models::meta::Token;
-use bytes::Bytes;
+use crate::config::settings::Scope;
+use crate::config::zilliqa::RPC_METHODS;
+use crate::models::audit::Change;
+use crate::models::meta::{ContractInit, Meta, Token};
+use crate::server::cache::CachePolicy;
+use crate::server::error::{json, ApiError, ApiResult, ErrorBody};
+use crate::server::snapshot::{ListedTokens, DEFAULT_TOKENS_LIMIT};
+use crate::server::state::AppState;
+use crate::utils::crypto::{from_bech32_address, to_bech32_address};
 use http_body_util::BodyExt;
-use http_body_util::Full;
-use hyper::header::ACCESS_CONTROL_ALLOW_METHODS;
-use hyper::header::ACCESS_CONTROL_ALLOW_ORIGIN;
-use hyper::http::HeaderValue;
-use hyper::{header, Request, Response, StatusCode};
-use serde_json::Value;
-use serde_json::{self, json};
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-
-use super::dex::ListedTokens;
-
-pub async fn handle_get_tokens(
-    req: Request<hyper::body::Incoming>,
-    meta: Arc<RwLock<Meta>>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut params_map = HashMap::new();
-    let query_params = req.uri().query().unwrap_or("");
-    let parsed_params = url::form_urlencoded::parse(query_params.as_bytes());
-
-    for (key, value) in parsed_params {
-        params_map.insert(key.into_owned(), value.into_owned());
-    }
-
-    let limit: usize = params_map
-        .get("limit")
-        .unwrap_or(&"200".to_string())
-        .parse()
-        .unwrap_or(200);
-    let token_type: u8 = params_map
-        .get("type")
-        .unwrap_or(&"1".to_string())
-        .parse()
-        .unwrap_or(1);
-    let offset: usize = params_map
-        .get("offset")
-        .unwrap_or(&"0".to_string())
-        .parse()
-        .unwrap_or(0);
-
-    for token in meta.read().await.list.iter() {
-        if token.token_type == token_type && token.status == 1 {
-            tokens.push(token.clone());
+use hyper::{Request, StatusCode};
+use log::info;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+use utoipa::{IntoParams, ToSchema};
+
+const BULK_MAX_ITEMS: usize = 1000;
+const MAX_TEXT_CHARS: usize = 64;
+
+/// Query of `GET /api/v1/tokens`; unparsable values fall back to the defaults.
+#[derive(Debug, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TokensQuery {
+    /// Page size.
+    #[param(default = 200)]
+    limit: Option<usize>,
+    /// Skipped tokens.
+    #[param(default = 0)]
+    offset: Option<usize>,
+    /// Token type to list.
+    #[param(rename = "type", default = 1)]
+    token_type: Option<u8>,
+}
+
+/// Token address path parameter: 20 hex bytes, with or without `0x`.
+/// Held lowercased with `0x`, however the client spelled it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Base16(pub String);
+
+impl FromStr for Base16 {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+
+        if digits.len() != 40 || !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(());
         }
+
+        Ok(Base16(format!("0x{}", digits.to_lowercase())))
     }
+}
+
+/// Body of `POST /api/v1/token`; name, symbol and decimals come from the
+/// contract.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TokenCreate {
+    /// Contract address, base16 or bech32.
+    #[schema(example = "zil1w7f636xqn5vf6n2zrnjmckekw3jkckkpyrd6z8")]
+    address: String,
+    #[serde(default)]
+    score: u8,
+}
+
+/// Invalid member of a token patch.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
 
-    let tokens = tokens
-        .into_iter()
-        .skip(offset)
-        .take(limit)
-        .collect::<Vec<Token>>();
-    let tokens_res = ListedTokens {
-        count: tokens.len(),
-        list: tokens,
+/// RFC 7396 merge patch of one token: every member replaces the token
+/// field of the same name. `score` is accepted for `scope`; the addresses
+/// are read-only and no field can be removed.
+#[derive(Debug, Clone)]
+struct TokenPatch(Map<String, Value>);
+
+/// Body of `POST /api/v1/tokens/bulk`.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BulkPatch {
+    #[schema(max_items = 1000)]
+    items: Vec<BulkItem>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BulkItem {
+    /// Token base16 address, case-insensitive.
+    base16: String,
+    /// Merge patch of the token.
+    #[schema(value_type = Object, example = json!({ "status": 0 }))]
+    patch: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BulkStatus {
+    Updated,
+    Unchanged,
+    Invalid,
+    /// Valid, but not applied because another item is invalid.
+    Skipped,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResult {
+    base16: String,
+    status: BulkStatus,
+    /// The patched token, when the item is valid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<Token>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResponse {
+    updated: usize,
+    /// In the order of the request items.
+    results: Vec<BulkResult>,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl TokenPatch {
+    fn parse(value: Value) -> Result<Self, Vec<FieldError>> {
+        match value {
+            Value::Object(members) => Ok(TokenPatch(members)),
+            _ => Err(vec![FieldError::new("", "patch must be a JSON object")]),
+        }
+    }
+
+    /// The patched token and the fields that changed, or every invalid
+    /// member.
+    fn apply(&self, token: &Token) -> Result<(Token, Vec<Change>), Vec<FieldError>> {
+        let mut patched = token.clone();
+        let mut changes = Vec::new();
+        let mut errors = Vec::new();
+
+        for (name, value) in &self.0 {
+            let field = if name == "score" {
+                "scope"
+            } else {
+                name.as_str()
+            };
+            let result = if name == "score" && self.0.contains_key("scope") {
+                Err("conflicts with `scope`".to_string())
+            } else {
+                set_field(&mut patched, field, value)
+            };
+
+            match result {
+                Ok((old_value, new_value)) if old_value != new_value => changes.push(Change {
+                    field: field.to_string(),
+                    old_value,
+                    new_value,
+                }),
+                Ok(_) => {}
+                Err(message) => errors.push(FieldError::new(name, message)),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok((patched, changes))
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Sets `field` of `token` to `value`, returning the previous value and the
+/// one stored, which is normalized.
+fn set_field(token: &mut Token, field: &str, value: &Value) -> Result<(Value, Value), String> {
+    let byte = || {
+        value
+            .as_u64()
+            .and_then(|v| u8::try_from(v).ok())
+            .ok_or("must be an integer from 0 to 255")
+    };
+    let text = || match value.as_str().map(str::trim) {
+        Some(v) if !v.is_empty() && v.chars().count() <= MAX_TEXT_CHARS => Ok(v.to_string()),
+        _ => Err(format!(
+            "must be a non-empty string of at most {} characters",
+            MAX_TEXT_CHARS
+        )),
     };
 
-    let res_json = serde_json::to_string(&tokens_res).unwrap();
-    let mut response = Response::builder()
-        .header(header::CONTENT_TYPE, "application/json")
-        .body(Full::new(Bytes::from(res_json)))
-        .unwrap();
+    if value.is_null() && field != "base16" && field != "bech32" && is_token_field(field) {
+        return Err("cannot be removed".to_string());
+    }
 
-    response
-        .headers_mut()
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    response.headers_mut().insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET"),
-    );
+    let values = match field {
+        "status" => {
+            let status = value.as_u64().filter(|v| *v <= 1).ok_or("must be 0 or 1")?;
 
-    Ok(response)
+            replace(&mut token.status, status as u8)
+        }
+        "scope" => replace(&mut token.scope, byte()?),
+        "decimals" => replace(&mut token.decimals, byte()?),
+        "token_type" => replace(&mut token.token_type, byte()?),
+        "listed" => replace(
+            &mut token.listed,
+            value.as_bool().ok_or("must be a boolean")?,
+        ),
+        "name" => replace(&mut token.name, text()?),
+        "symbol" => replace(&mut token.symbol, text()?),
+        "base16" | "bech32" => return Err("is read-only".to_string()),
+        _ => return Err("is not a token field".to_string()),
+    };
+
+    Ok(values)
 }
 
-pub async fn handle_get_token(
-    req: Request<hyper::body::Incoming>,
-    meta: Arc<RwLock<Meta>>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let params = req.uri().path().split("/").collect::<Vec<&str>>();
-    let symbol = params.last().unwrap_or(&"").to_lowercase();
+/// Stores `value` in `slot`, returning the previous and the new value.
+fn replace<T: Serialize>(slot: &mut T, value: T) -> (Value, Value) {
+    let new_value = json!(value);
 
-    if let Some(token) = meta
-        .read()
-        .await
-        .list
-        .iter()
-        .find(|t| t.symbol.to_lowercase() == symbol && t.status == 1)
-    {
-        let res_json = serde_json::to_string(&token).unwrap();
-        let response = Response::builder()
-            .header(header::CONTENT_TYPE, "application/json")
-            .body(Full::new(Bytes::from(res_json)))
-            .unwrap();
-
-        Ok(response)
-    } else {
-        let res = json!({
-            "code": -1,
-            "message": format!("No token {}", symbol)
-        });
-        let not_found = serde_json::to_string(&res).unwrap();
-        let response = Response::builder()
-            .header(header::CONTENT_TYPE, "application/json")
-            .status(StatusCode::NOT_FOUND)
-            .body(Full::new(Bytes::from(not_found)))
-            .unwrap();
-
-        Ok(response)
+    (json!(std::mem::replace(slot, value)), new_value)
+}
+
+fn is_token_field(field: &str) -> bool {
+    matches!(
+        field,
+        "status" | "scope" | "decimals" | "token_type" | "listed" | "name" | "symbol"
+    )
+}
+
+fn invalid_patch(errors: Vec<FieldError>) -> ApiError {
+    ApiError::BadRequest(
+        "Invalid token patch".to_string(),
+        Some(json!({ "errors": errors })),
+    )
+}
+
+impl TokensQuery {
+    fn parse(query: &str) -> Self {
+        let params: HashMap<_, _> = url::form_urlencoded::parse(query.as_bytes()).collect();
+
+        TokensQuery {
+            limit: params.get("limit").and_then(|v| v.parse().ok()),
+            offset: params.get("offset").and_then(|v| v.parse().ok()),
+            token_type: params.get("type").and_then(|v| v.parse().ok()),
+        }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/tokens",
+    tag = "tokens",
+    summary = "Enabled tokens, paginated",
+    params(TokensQuery),
+    responses((status = 200, body = ListedTokens))
+)]
+pub async fn handle_get_tokens(req: Request<hyper::body::Incoming>, state: AppState) -> ApiResult {
+    let query = TokensQuery::parse(req.uri().query().unwrap_or(""));
+    let limit = query.limit.unwrap_or(DEFAULT_TOKENS_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let token_type = query.token_type.unwrap_or(1);
+    let policy = CachePolicy::dataset(&state, &["meta", "dex", "rates"]);
+    let snapshot = state.snapshots.load();
+
+    if (token_type, offset, limit) == (1, 0, DEFAULT_TOKENS_LIMIT) {
+        return Ok(policy.apply(snapshot.tokens_body.response()));
+    }
+
+    json(
+        StatusCode::OK,
+        &snapshot.tokens_page(token_type, offset, limit),
+    )
+    .map(|r| policy.apply(r))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/token/{id}",
+    tag = "tokens",
+    summary = "Enabled token by symbol",
+    params(("id" = String, Path, description = "Token symbol, case-insensitive")),
+    responses(
+        (status = 200, body = Token),
+        (status = 404, description = "No enabled token with this symbol", body = ErrorBody)
+    )
+)]
+pub async fn handle_get_token(
+    _req: Request<hyper::body::Incoming>,
+    state: AppState,
+    symbol: String,
+) -> ApiResult {
+    let policy = CachePolicy::dataset(&state, &["meta"]);
+    let snapshot = state.snapshots.load();
+    let token = snapshot
+        .token_bodies
+        .get(&symbol)
+        .ok_or_else(|| ApiError::NotFound(format!("No token {}", symbol)))?;
+
+    Ok(policy.apply(token.response()))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/v1/token/{id}",
+    tag = "tokens",
+    summary = "Update token metadata with a JSON merge patch",
+    description = "RFC 7396 merge patch of `status` (0 or 1), `scope` (or `score`), `listed`, `name`, `symbol`, `decimals` and `token_type`. Every invalid member is reported and nothing is changed. `PUT` is accepted too.",
+    params(("id" = String, Path, description = "Token base16 address, case-insensitive")),
+    request_body(
+        content = Object,
+        content_type = "application/merge-patch+json",
+        example = json!({ "status": 0, "symbol": "ZIL" })
+    ),
+    responses(
+        (status = 200, description = "The patched token", body = Token),
+        (status = 400, description = "`bad_request` or `invalid_body`: `id` is not a token address, or the body is not a valid patch; `details.errors` lists the invalid members", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `tokens:write` scope", body = ErrorBody),
+        (status = 404, description = "`not_found`: no token with this address", body = ErrorBody),
+        (status = 500, description = "`internal_error`: the update could not be stored", body = ErrorBody)
+    ),
+    security(("admin_key" = ["tokens:write"]))
+)]
 pub async fn handle_update_token(
     req: Request<hyper::body::Incoming>,
-    meta: Arc<RwLock<Meta>>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let access_token = std::env::var("ACCESS_TOKEN").unwrap_or("666".to_string());
-    let header_token = match req.headers().get("Authorization") {
-        Some(value) => value.to_str().unwrap_or(""),
-        None => "",
-    };
-    let response = Response::builder().header(header::CONTENT_TYPE, "application/json");
+    state: AppState,
+    base16: String,
+) -> ApiResult {
+    let patched = update_token(req, state, &base16).await?;
 
-    if access_token != header_token {
-        let res = json!({
-            "code": -5,
-            "message": format!("Incorrect atuh token {header_token}")
-        });
-        let res_json = serde_json::to_string(&res).unwrap();
-        let response = response
-            .status(StatusCode::NETWORK_AUTHENTICATION_REQUIRED)
-            .body(Full::new(Bytes::from(res_json)))
-            .unwrap();
+    json(StatusCode::OK, &patched)
+}
 
-        return Ok(response);
+/// Applies the merge patch in the body of `req` to the token `base16`,
+/// returning the patched token.
+async fn update_token(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+    base16: &str,
+) -> Result<Token, ApiError> {
+    let admin = state.auth.authorize(&req, Scope::TokensWrite)?;
+    let body_bytes = req.collect().await?.to_bytes();
+    let value: Value = serde_json::from_slice(&body_bytes).map_err(|e| {
+        ApiError::BadRequest(
+            "Body is not valid JSON".to_string(),
+            Some(json!({ "error": e.to_string() })),
+        )
+    })?;
+    let patch = TokenPatch::parse(value).map_err(invalid_patch)?;
+    let mut token_meta = state.meta.write().await;
+    let token = token_meta
+        .get(base16)
+        .ok_or_else(|| ApiError::NotFound(format!("No token {}", base16)))?;
+    let (patched, changes) = patch.apply(token).map_err(invalid_patch)?;
+
+    if changes.is_empty() {
+        return Ok(patched);
     }
 
-    let params = req.uri().path().split("/").collect::<Vec<&str>>();
-    let base16 = params.last().unwrap_or(&"").to_lowercase();
+    token_meta
+        .edit(
+            &admin.id,
+            |meta| {
+                if let Some(token) = meta.get_mut(base16) {
+                    *token = patched.clone();
+                }
+
+                Ok(())
+            },
+            || {
+                state
+                    .audit
+                    .append(&admin.id, &patched.base16, changes)
+                    .map(drop)
+            },
+        )
+        .map_err(|e| ApiError::Internal(format!("tokens write: {}", e)))?;
+    state
+        .snapshots
+        .publish_tokens(token_meta.list.clone())
+        .map_err(|e| ApiError::Internal(format!("tokens snapshot: {}", e)))?;
+
+    info!("admin: `{}` updated token {}", admin.id, base16);
+
+    Ok(patched)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/token/{id}",
+    tag = "tokens",
+    summary = "Update token metadata with a JSON merge patch",
+    description = "The same merge patch as `PATCH`, validated the same way, answered with the message of old instead of the token.",
+    params(("id" = String, Path, description = "Token base16 address, case-insensitive")),
+    request_body(content = Object, example = json!({ "status": 0 })),
+    responses(
+        (status = 200, description = "Token updated", body = Object, example = json!({ "message": "updated token 0x…" })),
+        (status = 400, description = "`bad_request` or `invalid_body`: `id` is not a token address, or the body is not a valid patch; `details.errors` lists the invalid members", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `tokens:write` scope", body = ErrorBody),
+        (status = 404, description = "`not_found`: no token with this address", body = ErrorBody),
+        (status = 500, description = "`internal_error`: the update could not be stored", body = ErrorBody)
+    ),
+    security(("admin_key" = ["tokens:write"]))
+)]
+pub async fn handle_put_token(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+    base16: String,
+) -> ApiResult {
+    update_token(req, state, &base16).await?;
+
+    json(
+        StatusCode::OK,
+        &json!({ "message": format!("updated token {}", base16) }),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/tokens/bulk",
+    tag = "tokens",
+    summary = "Apply token merge patches atomically",
+    description = "Every item is validated first: either all patches are applied, as one revision, or none is and `details.results` reports each item.",
+    request_body = BulkPatch,
+    responses(
+        (status = 200, body = BulkResponse),
+        (status = 400, description = "`bad_request` or `invalid_body`: the body is invalid, or some items are and `details.results` holds a `BulkResult` per item", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `tokens:write` scope", body = ErrorBody),
+        (status = 500, description = "`internal_error`: the update could not be stored", body = ErrorBody)
+    ),
+    security(("admin_key" = ["tokens:write"]))
+)]
+pub async fn handle_bulk_update_tokens(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    let admin = state.auth.authorize(&req, Scope::TokensWrite)?;
     let body_bytes = req.collect().await?.to_bytes();
-    let value: Value = match serde_json::from_slice(&body_bytes) {
-        Ok(v) => v,
-        Err(_) => {
-            let res = json!({
-                "code": -2,
-                "message": "Incorrect params"
-            });
-            let res_json = serde_json::to_string(&res).unwrap();
-            let response = response
-                .status(StatusCode::BAD_REQUEST)
-                .body(Full::new(Bytes::from(res_json)))
-                .unwrap();
-
-            return Ok(response);
+    let bulk: BulkPatch = serde_json::from_slice(&body_bytes).map_err(|e| {
+        ApiError::BadRequest(
+            "Body is not a valid bulk patch".to_string(),
+            Some(json!({ "error": e.to_string() })),
+        )
+    })?;
+
+    if bulk.items.len() > BULK_MAX_ITEMS {
+        return Err(ApiError::bad_request(format!(
+            "At most {} items per batch",
+            BULK_MAX_ITEMS
+        )));
+    }
+
+    let mut token_meta = state.meta.write().await;
+    let mut seen = HashSet::new();
+    let mut staged = Vec::new();
+    let mut results = Vec::with_capacity(bulk.items.len());
+
+    for item in bulk.items {
+        let outcome = match token_meta.get(&item.base16) {
+            None => Err(vec![FieldError::new("base16", "no such token")]),
+            Some(token) if !seen.insert(token.base16.to_lowercase()) => Err(vec![FieldError::new(
+                "base16",
+                "appears twice in the batch",
+            )]),
+            Some(token) => TokenPatch::parse(item.patch).and_then(|p| p.apply(token)),
+        };
+        let result = match outcome {
+            Ok((patched, changes)) => {
+                let status = if changes.is_empty() {
+                    BulkStatus::Unchanged
+                } else {
+                    BulkStatus::Updated
+                };
+
+                staged.push((patched.clone(), changes));
+
+                BulkResult {
+                    base16: item.base16,
+                    status,
+                    token: Some(patched),
+                    errors: Vec::new(),
+                }
+            }
+            Err(errors) => BulkResult {
+                base16: item.base16,
+                status: BulkStatus::Invalid,
+                token: None,
+                errors,
+            },
+        };
+
+        results.push(result);
+    }
+
+    if results.iter().any(|r| r.status == BulkStatus::Invalid) {
+        for result in &mut results {
+            if result.status == BulkStatus::Updated {
+                result.status = BulkStatus::Skipped;
+            }
         }
+
+        return Err(ApiError::BadRequest(
+            "Invalid items, no token was updated".to_string(),
+            Some(json!({ "results": results })),
+        ));
+    }
+
+    staged.retain(|(_, changes)| !changes.is_empty());
+
+    if !staged.is_empty() {
+        token_meta
+            .edit(
+                &admin.id,
+                |meta| {
+                    for (patched, _) in &staged {
+                        if let Some(token) = meta.get_mut(&patched.base16) {
+                            *token = patched.clone();
+                        }
+                    }
+
+                    Ok(())
+                },
+                || {
+                    let changes = staged
+                        .iter()
+                        .map(|(patched, changes)| (patched.base16.clone(), changes.clone()))
+                        .collect();
+
+                    state.audit.append_all(&admin.id, changes).map(drop)
+                },
+            )
+            .map_err(|e| ApiError::Internal(format!("tokens write: {}", e)))?;
+        state
+            .snapshots
+            .publish_tokens(token_meta.list.clone())
+            .map_err(|e| ApiError::Internal(format!("tokens snapshot: {}", e)))?;
+    }
+
+    info!(
+        "admin: `{}` updated {} tokens in bulk",
+        admin.id,
+        staged.len()
+    );
+
+    json(
+        StatusCode::OK,
+        &BulkResponse {
+            updated: staged.len(),
+            results,
+        },
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/token",
+    tag = "tokens",
+    summary = "Add a ZRC-2 token by hand",
+    description = "The token is read from its contract init and pinned: the sync never drops or overwrites it.",
+    request_body = TokenCreate,
+    responses(
+        (status = 201, description = "Token added", body = Token),
+        (status = 400, description = "`bad_request` or `invalid_body`: invalid body, or the address is not a ZRC-2 contract", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `tokens:write` scope", body = ErrorBody),
+        (status = 409, description = "`conflict`: the token is already listed", body = ErrorBody),
+        (status = 502, description = "`upstream_error`: no Zilliqa node answered", body = ErrorBody)
+    ),
+    security(("admin_key" = ["tokens:write"]))
+)]
+pub async fn handle_create_token(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+) -> ApiResult {
+    let admin = state.auth.authorize(&req, Scope::TokensWrite)?;
+    let body_bytes = req.collect().await?.to_bytes();
+    let create: TokenCreate = serde_json::from_slice(&body_bytes).map_err(|e| {
+        ApiError::BadRequest(
+            "Body is not a valid token".to_string(),
+            Some(json!({ "error": e.to_string() })),
+        )
+    })?;
+    let address = parse_address(&create.address)
+        .ok_or_else(|| ApiError::bad_request(format!("Invalid address {}", create.address)))?;
+    let exists = |meta: &Meta| {
+        meta.get(&address)
+            .map(|t| ApiError::Conflict(format!("Token {} is already listed", t.base16)))
     };
-    let map = match value.as_object() {
-        Some(v) => v,
-        None => {
-            let res = json!({
-                "code": -2,
-                "message": "Incorrect params"
-            });
-            let res_json = serde_json::to_string(&res).unwrap();
-            let response = response
-                .status(StatusCode::BAD_REQUEST)
-                .body(Full::new(Bytes::from(res_json)))
-                .unwrap();
-
-            return Ok(response);
-        }
+
+    // Checked before the node is asked, and again once the lock is held.
+    if let Some(conflict) = exists(&*state.meta.read().await) {
+        return Err(conflict);
+    }
+
+    let init = state
+        .zilliqa
+        .fetch_single::<Vec<ContractInit>>(RPC_METHODS.get_smart_contract_init, json!([address]))
+        .await
+        .map_err(|e| ApiError::Upstream(format!("zilliqa node: {}", e)))?;
+    let invalid = |e: String| {
+        ApiError::BadRequest(
+            format!("{} is not a ZRC-2 contract", create.address),
+            Some(json!({ "error": e })),
+        )
     };
-    let status = map.get("status");
-    let score = map.get("score");
-    let listed = map.get("listed");
-    let symbol = map.get("symbol");
-    let mut token_meta = meta.write().await;
-    let token_index = match token_meta
-        .list
-        .iter()
-        .position(|t| t.base16.to_lowercase() == base16)
-    {
-        Some(index) => index,
-        None => {
-            let res = json!({
-                "code": -1,
-                "message": format!("No token {}", base16)
-            });
-            let not_found = serde_json::to_string(&res).unwrap();
-            let response = response
-                .status(StatusCode::NOT_FOUND)
-                .body(Full::new(Bytes::from(not_found)))
-                .unwrap();
-
-            return Ok(response);
-        }
+    let params = init.get_result().map_err(|e| invalid(e.to_string()))?;
+    let (name, symbol, base16, decimals) =
+        Meta::parse_init(params).map_err(|e| invalid(e.to_string()))?;
+    let token = Token {
+        bech32: to_bech32_address(&base16)
+            .ok_or_else(|| invalid(format!("invalid _this_address {}", base16)))?,
+        listed: state.snapshots.load().pools.contains_key(&base16),
+        base16,
+        scope: create.score,
+        name,
+        symbol,
+        token_type: 1,
+        decimals,
+        status: 1,
     };
+    let mut token_meta = state.meta.write().await;
+
+    if let Some(conflict) = exists(&token_meta) {
+        return Err(conflict);
+    }
+
+    token_meta
+        .edit(
+            &admin.id,
+            |meta| meta.insert_manual(token.clone()),
+            || {
+                let change = Change {
+                    field: "token".to_string(),
+                    old_value: Value::Null,
+                    new_value: json!(token),
+                };
+
+                state
+                    .audit
+                    .append(&admin.id, &token.base16, vec![change])
+                    .map(drop)
+            },
+        )
+        .map_err(|e| ApiError::Internal(format!("tokens write: {}", e)))?;
+    state
+        .snapshots
+        .publish_tokens(token_meta.list.clone())
+        .map_err(|e| ApiError::Internal(format!("tokens snapshot: {}", e)))?;
+
+    info!("admin: `{}` added token {}", admin.id, token.base16);
+
+    json(StatusCode::CREATED, &token)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/token/{id}",
+    tag = "tokens",
+    summary = "Delete a token",
+    description = "The address stays pinned as deleted, so the sync does not add the token back.",
+    params(("id" = String, Path, description = "Token base16 address, case-insensitive")),
+    responses(
+        (status = 200, description = "Token deleted", body = Object, example = json!({ "message": "deleted token 0x…" })),
+        (status = 400, description = "`bad_request`: `id` is not a token address", body = ErrorBody),
+        (status = 401, description = "`unauthorized`: missing or unknown admin key", body = ErrorBody),
+        (status = 403, description = "`forbidden`: the key lacks the `tokens:write` scope", body = ErrorBody),
+        (status = 404, description = "`not_found`: no token with this address", body = ErrorBody),
+        (status = 500, description = "`internal_error`: the deletion could not be stored", body = ErrorBody)
+    ),
+    security(("admin_key" = ["tokens:write"]))
+)]
+pub async fn handle_delete_token(
+    req: Request<hyper::body::Incoming>,
+    state: AppState,
+    base16: String,
+) -> ApiResult {
+    let admin = state.auth.authorize(&req, Scope::TokensWrite)?;
+    let mut token_meta = state.meta.write().await;
+    let token = token_meta
+        .get(&base16)
+        .cloned()
+        .ok_or_else(|| ApiError::NotFound(format!("No token {}", base16)))?;
 
-    if let Some(status) = status {
-        let new_status = status.as_u64().unwrap_or(0);
-        let new_status: u8 = if new_status > 1 { 1 } else { new_status as u8 };
+    token_meta
+        .edit(
+            &admin.id,
+            |meta| meta.remove_manual(&base16),
+            || {
+                let change = Change {
+                    field: "token".to_string(),
+                    old_value: json!(token),
+                    new_value: Value::Null,
+                };
 
-        token_meta.list[token_index].status = new_status;
+                state
+                    .audit
+                    .append(&admin.id, &token.base16, vec![change])
+                    .map(drop)
+            },
+        )
+        .map_err(|e| ApiError::Internal(format!("tokens write: {}", e)))?;
+    state
+        .snapshots
+        .publish_tokens(token_meta.list.clone())
+        .map_err(|e| ApiError::Internal(format!("tokens snapshot: {}", e)))?;
+
+    info!("admin: `{}` deleted token {}", admin.id, token.base16);
+
+    json(
+        StatusCode::OK,
+        &json!({ "message": format!("deleted token {}", token.base16) }),
+    )
+}
+
+/// Lowercase hex address without `0x`, from a base16 or bech32 address.
+fn parse_address(address: &str) -> Option<String> {
+    let address = address.trim().to_lowercase();
+
+    if address.starts_with("zil1") {
+        return from_bech32_address(&address).map(hex::encode);
     }
-    if let Some(symbol) = symbol {
-        let new_symbol = symbol.as_str().unwrap_or("").to_string();
 
-        token_meta.list[token_index].symbol = new_symbol;
+    let hex = address.trim_start_matches("0x");
+
+    (hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit())).then(|| hex.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token() -> Token {
+        Token {
+            bech32: "zil1w7f636xqn5vf6n2zrnjmckekw3jkckkpyrd6z8".to_string(),
+            base16: "0x7793a8e8c09d189d4d421ce5bc5b3674656c5ac1".to_string(),
+            scope: 5,
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            token_type: 1,
+            decimals: 12,
+            listed: false,
+            status: 1,
+        }
     }
-    if let Some(score) = score {
-        let new_score: u8 = score.as_u64().unwrap_or(0) as u8;
 
-        token_meta.list[token_index].scope = new_score;
+    #[test]
+    fn test_base16_param() {
+        assert_eq!(
+            "0x7793A8E8C09D189D4D421CE5BC5B3674656C5AC1".parse(),
+            Ok(Base16(token().base16))
+        );
+        assert_eq!(
+            "7793a8e8c09d189d4d421ce5bc5b3674656c5ac1".parse(),
+            Ok(Base16(token().base16))
+        );
+        assert_eq!(
+            "0X7793a8e8c09d189d4d421ce5bc5b3674656c5ac1".parse(),
+            Ok(Base16(token().base16))
+        );
+        assert!("0x7793a8e8".parse::<Base16>().is_err());
+        assert!("zil1w7f636xqn5vf6n2zrnjmckekw3jkckkpyrd6z8"
+            .parse::<Base16>()
+            .is_err());
     }
-    if let Some(listed) = listed {
-        let new_listed = listed.as_bool().unwrap_or(false);
 
-        token_meta.list[token_index].listed = new_listed;
+    fn patch(value: Value) -> TokenPatch {
+        TokenPatch::parse(value).unwrap()
     }
 
-    match token_meta.write_db() {
-        Ok(_) => (),
-        Err(_) => {
-            let res = json!({
-                "code": -4,
-                "message": "Cannot write database"
-            });
-            let wr_db = serde_json::to_string(&res).unwrap();
-            let response = response
-                .status(StatusCode::BAD_REQUEST)
-                .body(Full::new(Bytes::from(wr_db)))
-                .unwrap();
+    #[test]
+    fn test_merge_patch() {
+        let (patched, changes) = patch(json!({
+            "status": 0,
+            "score": 9,
+            "name": " Renamed ",
+            "decimals": 12,
+            "token_type": 2
+        }))
+        .apply(&token())
+        .unwrap();
 
-            return Ok(response);
-        }
-    };
+        assert_eq!(
+            (patched.status, patched.scope, patched.name.as_str()),
+            (0, 9, "Renamed")
+        );
+        assert_eq!(patched.token_type, 2);
+        // Unchanged values are not recorded.
+        assert_eq!(
+            changes.iter().map(|c| c.field.as_str()).collect::<Vec<_>>(),
+            vec!["name", "scope", "status", "token_type"]
+        );
+        // The trail holds the stored, trimmed value.
+        assert_eq!(changes[0].new_value, json!("Renamed"));
 
-    let res = json!({ "message": format!("updated token {}", base16) });
-    let ok = serde_json::to_string(&res).unwrap();
-    let response = response
-        .status(StatusCode::OK)
-        .body(Full::new(Bytes::from(ok)))
-        .unwrap();
+        let (_, changes) = patch(json!({ "name": " Token " })).apply(&token()).unwrap();
+        assert!(changes.is_empty());
+    }
 
-    return Ok(response);
+    #[test]
+    fn test_invalid_members_are_all_reported() {
+        let errors = patch(json!({
+            "status": 2,
+            "decimals": 300,
+            "symbol": "",
+            "listed": null,
+            "base16": "0x00",
+            "color": "red"
+        }))
+        .apply(&token())
+        .unwrap_err();
+
+        assert_eq!(
+            errors.iter().map(|e| e.field.as_str()).collect::<Vec<_>>(),
+            vec!["base16", "color", "decimals", "listed", "status", "symbol"]
+        );
+        assert_eq!(errors[3].message, "cannot be removed");
+        assert!(TokenPatch::parse(json!([1])).is_err());
+    }
 }
@@ -0,0 +1,63 @@
+use log::{error, info};
+use tokio_util::sync::CancellationToken;
+
+/// Resolves on the first SIGINT or SIGTERM.
+pub async fn terminate() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("signal: cannot listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let term = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(e) => {
+                error!("signal: cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let term = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("signal: SIGINT received"),
+        _ = term => info!("signal: SIGTERM received"),
+    }
+}
+
+/// Calls `on_hangup` for every SIGHUP until `shutdown` is cancelled.
+#[cfg(unix)]
+pub async fn hangup(shutdown: CancellationToken, on_hangup: impl Fn()) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut stream = match signal(SignalKind::hangup()) {
+        Ok(stream) => stream,
+        Err(e) => {
+            error!("signal: cannot listen for SIGHUP: {}", e);
+
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            Some(()) = stream.recv() => {
+                info!("signal: SIGHUP received");
+                on_hangup();
+            }
+            _ = shutdown.cancelled() => return,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub async fn hangup(_shutdown: CancellationToken, _on_hangup: impl Fn()) {}
@@ -0,0 +1,499 @@
+use arc_swap::ArcSwap;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{
+    header::{self, HeaderValue},
+    Response, StatusCode,
+};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+use utoipa::ToSchema;
+
+use super::{cache::etag, error::bytes_response};
+use crate::{
+    models::{changes::Changes, currencies::Rates, meta::Token},
+    utils::time::now_secs,
+};
+
+/// Page size of `GET /api/v1/tokens` without a `limit`.
+pub const DEFAULT_TOKENS_LIMIT: usize = 200;
+/// Datasets of a snapshot, named after the jobs that update them.
+pub const DATASETS: [&str; 3] = ["meta", "dex", "rates"];
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListedTokens {
+    pub count: usize,
+    pub list: Vec<ListedToken>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListedToken {
+    #[serde(flatten)]
+    pub token: Token,
+    /// Moves of the ZIL price implied by the dex pool; absent without a
+    /// pool or price history.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Changes>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DexResponse {
+    tokens: ListedTokens,
+    /// Pool reserves keyed by token base16: `[zil_reserve, token_reserve]`.
+    pools: HashMap<String, (String, String)>,
+    /// ZIL price in USD.
+    rate: String,
+}
+
+/// A JSON body serialized once, with its ETag.
+#[derive(Debug, Clone)]
+pub struct Rendered {
+    pub body: Bytes,
+    pub etag: String,
+}
+
+/// Immutable view of every dataset, with the bodies of the common queries
+/// already rendered.
+#[derive(Debug)]
+pub struct Snapshot {
+    /// Every token, in `Meta` order.
+    pub tokens: Vec<Token>,
+    pub pools: HashMap<String, (u128, u128)>,
+    pub rates: Rates,
+    /// Changes of the token prices, by lowercase base16.
+    pub token_changes: HashMap<String, Changes>,
+    /// `GET /api/v1/dex`.
+    pub dex_body: Rendered,
+    /// `GET /api/v1/rates`.
+    pub rates_body: Rendered,
+    /// `GET /api/v1/rates?details=1`.
+    pub rates_with_details_body: Rendered,
+    /// `GET /api/v1/rates/details`.
+    pub rates_details_body: Rendered,
+    /// `GET /api/v1/tokens` with the default query.
+    pub tokens_body: Rendered,
+    /// `GET /api/v1/token/{id}`, keyed by lowercase symbol.
+    pub token_bodies: HashMap<String, Rendered>,
+    /// When each dataset was last published, in unix seconds, keyed by the
+    /// job that updates it: `meta`, `dex` or `rates`.
+    pub published: HashMap<&'static str, u64>,
+}
+
+/// The current `Snapshot`. Readers never block; the updaters replace it
+/// whole after every change.
+pub struct Snapshots {
+    current: ArcSwap<Snapshot>,
+    // Serializes publishers so that two updaters cannot drop each
+    // other's dataset.
+    publish: Mutex<()>,
+}
+
+impl Rendered {
+    pub fn new<T: Serialize + ?Sized>(value: &T) -> Result<Self, serde_json::Error> {
+        let body = Bytes::from(serde_json::to_vec(value)?);
+
+        Ok(Rendered {
+            etag: etag(&body),
+            body,
+        })
+    }
+
+    /// A 200 carrying the body and its ETag, so revalidation skips hashing.
+    pub fn response(&self) -> Response<Full<Bytes>> {
+        let mut response = bytes_response(StatusCode::OK, "application/json", self.body.clone());
+
+        if let Ok(value) = HeaderValue::from_str(&self.etag) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+
+        response
+    }
+}
+
+impl Snapshot {
+    pub fn build(
+        tokens: Vec<Token>,
+        pools: HashMap<String, (u128, u128)>,
+        rates: Rates,
+        token_changes: HashMap<String, Changes>,
+    ) -> Result<Self, serde_json::Error> {
+        Self::assemble(tokens, pools, rates, token_changes, None)
+    }
+
+    /// Like `build`, reusing `token_bodies` when they were rendered from
+    /// the same `tokens`.
+    fn assemble(
+        tokens: Vec<Token>,
+        pools: HashMap<String, (u128, u128)>,
+        rates: Rates,
+        token_changes: HashMap<String, Changes>,
+        token_bodies: Option<HashMap<String, Rendered>>,
+    ) -> Result<Self, serde_json::Error> {
+        let dex_body = Rendered::new(&dex_response(
+            &tokens,
+            &pools,
+            &rates.prices,
+            &token_changes,
+        ))?;
+        let rates_body = Rendered::new(&rates.prices)?;
+        let rates_with_details_body = Rendered::new(&rates)?;
+        let rates_details_body = Rendered::new(&rates.details())?;
+        let tokens_body = Rendered::new(&tokens_page(
+            &tokens,
+            &token_changes,
+            1,
+            0,
+            DEFAULT_TOKENS_LIMIT,
+        ))?;
+        let token_bodies = match token_bodies {
+            Some(token_bodies) => token_bodies,
+            None => render_tokens(&tokens)?,
+        };
+
+        Ok(Snapshot {
+            tokens,
+            pools,
+            rates,
+            token_changes,
+            dex_body,
+            rates_body,
+            rates_with_details_body,
+            rates_details_body,
+            tokens_body,
+            token_bodies,
+            published: DATASETS.iter().map(|name| (*name, now_secs())).collect(),
+        })
+    }
+
+    /// Whether every rendered body is the same as in `other`.
+    fn renders_like(&self, other: &Snapshot) -> bool {
+        let same = |a: &Rendered, b: &Rendered| a.etag == b.etag;
+
+        same(&self.dex_body, &other.dex_body)
+            && same(&self.rates_body, &other.rates_body)
+            && same(
+                &self.rates_with_details_body,
+                &other.rates_with_details_body,
+            )
+            && same(&self.rates_details_body, &other.rates_details_body)
+            && same(&self.tokens_body, &other.tokens_body)
+            && self.token_bodies.len() == other.token_bodies.len()
+            && self.token_bodies.iter().all(|(symbol, rendered)| {
+                other
+                    .token_bodies
+                    .get(symbol)
+                    .is_some_and(|other| same(rendered, other))
+            })
+    }
+
+    /// Enabled tokens of `token_type`, paginated.
+    pub fn tokens_page(&self, token_type: u8, offset: usize, limit: usize) -> ListedTokens {
+        tokens_page(&self.tokens, &self.token_changes, token_type, offset, limit)
+    }
+}
+
+impl Snapshots {
+    pub fn new(snapshot: Snapshot) -> Self {
+        Snapshots {
+            current: ArcSwap::from_pointee(snapshot),
+            publish: Mutex::new(()),
+        }
+    }
+
+    pub fn load(&self) -> Arc<Snapshot> {
+        self.current.load_full()
+    }
+
+    pub fn publish_tokens(&self, tokens: Vec<Token>) -> Result<(), serde_json::Error> {
+        self.publish("meta", |current| {
+            Snapshot::build(
+                tokens,
+                current.pools.clone(),
+                current.rates.clone(),
+                current.token_changes.clone(),
+            )
+        })
+    }
+
+    /// Publishes new pools along with the token changes computed from them.
+    pub fn publish_pools(
+        &self,
+        pools: HashMap<String, (u128, u128)>,
+        token_changes: HashMap<String, Changes>,
+    ) -> Result<(), serde_json::Error> {
+        self.publish("dex", |current| {
+            Snapshot::assemble(
+                current.tokens.clone(),
+                pools,
+                current.rates.clone(),
+                token_changes,
+                Some(current.token_bodies.clone()),
+            )
+        })
+    }
+
+    /// Publishes new rates along with the token changes computed from them.
+    pub fn publish_rates(
+        &self,
+        rates: Rates,
+        token_changes: HashMap<String, Changes>,
+    ) -> Result<(), serde_json::Error> {
+        self.publish("rates", |current| {
+            Snapshot::assemble(
+                current.tokens.clone(),
+                current.pools.clone(),
+                rates,
+                token_changes,
+                Some(current.token_bodies.clone()),
+            )
+        })
+    }
+
+    /// Replaces the snapshot with the one `build` makes from the current
+    /// one, stamping `dataset` as published now unless no body changed.
+    fn publish(
+        &self,
+        dataset: &'static str,
+        build: impl FnOnce(&Snapshot) -> Result<Snapshot, serde_json::Error>,
+    ) -> Result<(), serde_json::Error> {
+        let _guard = self.publish.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.current.load();
+        let mut next = build(&current)?;
+
+        next.published = current.published.clone();
+
+        if !next.renders_like(&current) {
+            next.published.insert(dataset, now_secs());
+        }
+
+        self.current.store(Arc::new(next));
+
+        Ok(())
+    }
+}
+
+fn render_tokens(tokens: &[Token]) -> Result<HashMap<String, Rendered>, serde_json::Error> {
+    let mut token_bodies = HashMap::new();
+
+    // The first enabled token wins a shared symbol, as in a linear search.
+    for token in tokens.iter().filter(|t| t.status == 1) {
+        if let Entry::Vacant(entry) = token_bodies.entry(token.symbol.to_lowercase()) {
+            entry.insert(Rendered::new(token)?);
+        }
+    }
+
+    Ok(token_bodies)
+}
+
+fn tokens_page(
+    tokens: &[Token],
+    changes: &HashMap<String, Changes>,
+    token_type: u8,
+    offset: usize,
+    limit: usize,
+) -> ListedTokens {
+    let list = tokens
+        .iter()
+        .filter(|t| t.token_type == token_type && t.status == 1)
+        .skip(offset)
+        .take(limit)
+        .map(|t| listed_token(t.clone(), changes))
+        .collect::<Vec<ListedToken>>();
+
+    ListedTokens {
+        count: list.len(),
+        list,
+    }
+}
+
+fn dex_response(
+    tokens: &[Token],
+    pools: &HashMap<String, (u128, u128)>,
+    rates: &Map<String, Value>,
+    changes: &HashMap<String, Changes>,
+) -> DexResponse {
+    let zilliqa = Token {
+        bech32: String::from("zil1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqq9yf6pz"),
+        base16: String::from("0x0000000000000000000000000000000000000000"),
+        decimals: 12,
+        symbol: String::from("ZIL"),
+        name: String::from("Zilliqa"),
+        token_type: 1,
+        status: 1,
+        scope: 100,
+        listed: true,
+    };
+    let list = std::iter::once(zilliqa)
+        .chain(
+            tokens
+                .iter()
+                .filter(|t| t.listed && t.token_type == 1 && t.status == 1)
+                .cloned(),
+        )
+        .map(|t| listed_token(t, changes))
+        .collect::<Vec<ListedToken>>();
+    let pools = pools
+        .iter()
+        .map(|(key, values)| {
+            (
+                key.to_string(),
+                (values.0.to_string(), values.1.to_string()),
+            )
+        })
+        .collect();
+
+    DexResponse {
+        tokens: ListedTokens {
+            count: list.len(),
+            list,
+        },
+        pools,
+        rate: rates.get("usd").unwrap_or(&json!("0")).to_string(),
+    }
+}
+
+fn listed_token(token: Token, changes: &HashMap<String, Changes>) -> ListedToken {
+    ListedToken {
+        changes: changes.get(&token.base16.to_lowercase()).cloned(),
+        token,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::currencies::Prices;
+
+    fn token(symbol: &str, base16: &str, status: u8, listed: bool) -> Token {
+        Token {
+            bech32: String::new(),
+            base16: base16.to_string(),
+            scope: 0,
+            name: symbol.to_string(),
+            symbol: symbol.to_string(),
+            token_type: 1,
+            decimals: 12,
+            listed,
+            status,
+        }
+    }
+
+    fn body(rendered: &Rendered) -> Value {
+        serde_json::from_slice(&rendered.body).unwrap()
+    }
+
+    #[test]
+    fn test_build_renders_queries() {
+        let tokens = vec![
+            token("gZIL", "0x01", 1, true),
+            token("XSGD", "0x02", 1, false),
+            token("gzil", "0x03", 1, true),
+            token("OLD", "0x04", 0, true),
+        ];
+        let pools = HashMap::from([("0x01".to_string(), (10, 20))]);
+        let rates = Rates {
+            prices: Prices(Map::from_iter([("usd".to_string(), json!(0.02))])),
+            ..Default::default()
+        };
+        let changes = HashMap::from([(
+            "0x01".to_string(),
+            Changes::new(3.0, [Some(2.0), None, None]),
+        )]);
+        let snapshot = Snapshot::build(tokens, pools, rates, changes).unwrap();
+        let dex = body(&snapshot.dex_body);
+
+        assert_eq!(dex["tokens"]["count"], 3);
+        assert_eq!(dex["tokens"]["list"][0]["symbol"], "ZIL");
+        assert_eq!(dex["pools"]["0x01"], json!(["10", "20"]));
+        assert_eq!(dex["rate"], "0.02");
+        assert_eq!(dex["tokens"]["list"][1]["changes"]["1h"]["percent"], 50.0);
+        assert!(dex["tokens"]["list"][2].get("changes").is_none());
+        assert_eq!(
+            body(&snapshot.tokens_body)["list"][0]["changes"]["1h"]["absolute"],
+            1.0
+        );
+        assert_eq!(body(&snapshot.tokens_body)["count"], 3);
+        assert_eq!(body(&snapshot.token_bodies["gzil"])["base16"], "0x01");
+        assert!(!snapshot.token_bodies.contains_key("old"));
+        assert_eq!(snapshot.rates_body.etag, etag(&snapshot.rates_body.body));
+        assert_eq!(body(&snapshot.rates_body), json!({ "usd": 0.02 }));
+        assert_eq!(
+            body(&snapshot.rates_with_details_body),
+            json!({ "usd": 0.02, "sources": {}, "changes": {}, "stale": {} })
+        );
+        assert_eq!(
+            body(&snapshot.rates_details_body),
+            json!({ "sources": {}, "changes": {}, "stale": {} })
+        );
+        assert_eq!(snapshot.tokens_page(1, 1, 1).list[0].token.symbol, "XSGD");
+    }
+
+    #[test]
+    fn test_publish_keeps_other_datasets() {
+        let mut snapshot = Snapshot::build(
+            vec![token("gZIL", "0x01", 1, true)],
+            HashMap::new(),
+            Rates::default(),
+            HashMap::new(),
+        )
+        .unwrap();
+
+        snapshot.published.values_mut().for_each(|t| *t = 0);
+
+        let snapshots = Snapshots::new(snapshot);
+        let before = snapshots.load();
+
+        snapshots
+            .publish_rates(
+                Rates {
+                    prices: Prices(Map::from_iter([("usd".to_string(), json!(0.03))])),
+                    ..Default::default()
+                },
+                HashMap::new(),
+            )
+            .unwrap();
+
+        let after = snapshots.load();
+
+        assert_eq!(after.tokens.len(), 1);
+        assert_eq!(body(&after.dex_body)["rate"], "0.03");
+        assert_ne!(before.dex_body.etag, after.dex_body.etag);
+        // Readers holding the old snapshot keep a consistent view.
+        assert_eq!(body(&before.dex_body)["rate"], "\"0\"");
+        // Only the published dataset counts as modified.
+        assert!(after.published["rates"] > 0);
+        assert_eq!((after.published["meta"], after.published["dex"]), (0, 0));
+
+        snapshots
+            .publish_pools(
+                HashMap::from([("0x01".to_string(), (2, 1))]),
+                HashMap::from([(
+                    "0x01".to_string(),
+                    Changes::new(2.0, [None, Some(4.0), None]),
+                )]),
+            )
+            .unwrap();
+
+        let latest = snapshots.load();
+        let dex = body(&latest.dex_body);
+        assert_eq!(dex["rate"], "0.03");
+        assert_eq!(latest.pools.len(), 1);
+        // Token bodies are not rendered again for other datasets.
+        assert_eq!(
+            latest.token_bodies["gzil"].body.as_ptr(),
+            before.token_bodies["gzil"].body.as_ptr()
+        );
+        assert_eq!(dex["tokens"]["list"][1]["changes"]["24h"]["percent"], -50.0);
+
+        // Publishing the same content again is no modification.
+        let published = latest.published.clone();
+        snapshots
+            .publish_tokens(vec![token("gZIL", "0x01", 1, true)])
+            .unwrap();
+        assert_eq!(snapshots.load().published, published);
+    }
+}
@@ -0,0 +1,64 @@
+use std::{sync::Arc, time::Instant};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::settings::Settings,
+    models::{audit::AuditLog, candles::Candles, history::RatesHistory, meta::Meta},
+    scheduler::Scheduler,
+    server::{
+        auth::AdminAuth, compression::Compressor, rate_limit::RateLimiter, snapshot::Snapshots,
+    },
+    utils::zilliqa::Zilliqa,
+};
+
+/// Everything a request handler may need, cheap to clone per connection.
+///
+/// Reads go through `snapshots`; `meta` is only locked by handlers that
+/// change it.
+#[derive(Clone)]
+pub struct AppState {
+    pub meta: Arc<RwLock<Meta>>,
+    pub snapshots: Arc<Snapshots>,
+    pub audit: Arc<AuditLog>,
+    pub series: Series,
+    pub zilliqa: Zilliqa,
+    pub settings: Arc<Settings>,
+    pub auth: Arc<AdminAuth>,
+    pub scheduler: Arc<Scheduler>,
+    pub compressor: Arc<Compressor>,
+    pub limiter: Arc<RateLimiter>,
+    pub started_at: Instant,
+}
+
+/// Stored price series, appended to by the rates and dex updaters.
+#[derive(Clone)]
+pub struct Series {
+    pub history: Arc<RatesHistory>,
+    pub candles: Arc<Candles>,
+}
+
+impl AppState {
+    pub fn new(
+        meta: Arc<RwLock<Meta>>,
+        snapshots: Arc<Snapshots>,
+        audit: Arc<AuditLog>,
+        series: Series,
+        zilliqa: Zilliqa,
+        settings: Arc<Settings>,
+        scheduler: Arc<Scheduler>,
+    ) -> Self {
+        AppState {
+            meta,
+            snapshots,
+            audit,
+            series,
+            zilliqa,
+            auth: Arc::new(AdminAuth::new(&settings)),
+            compressor: Arc::new(Compressor::new(&settings.server)),
+            limiter: Arc::new(RateLimiter::new(&settings.rate_limit)),
+            settings,
+            scheduler,
+            started_at: Instant::now(),
+        }
+    }
+}
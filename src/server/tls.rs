@@ -0,0 +1,212 @@
+use rustls_pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use thiserror::Error;
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+        ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::config::settings::{ListenerSettings, TlsSettings};
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("Cannot read {0}: {1}")]
+    Pem(PathBuf, String),
+
+    #[error("No certificate found in {0}")]
+    NoCertificate(PathBuf),
+
+    #[error("Unsupported private key in {0}: {1}")]
+    Key(PathBuf, String),
+
+    #[error("Certificate {0} does not match key {1}: {2}")]
+    Mismatch(PathBuf, PathBuf, String),
+
+    #[error("TLS config: {0}")]
+    Config(#[from] tokio_rustls::rustls::Error),
+}
+
+/// Serves the current certificate and swaps it in place on `reload`, so
+/// new handshakes pick up renewed files without restarting listeners.
+#[derive(Debug)]
+pub struct CertResolver {
+    settings: TlsSettings,
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn load(settings: &TlsSettings) -> Result<Self, TlsError> {
+        let key = load_certified_key(settings)?;
+
+        Ok(CertResolver {
+            settings: settings.clone(),
+            key: RwLock::new(Arc::new(key)),
+        })
+    }
+
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let key = load_certified_key(&self.settings)?;
+
+        *self.key.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+
+        Ok(())
+    }
+
+    pub fn cert_path(&self) -> &str {
+        &self.settings.cert
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(Arc::clone(
+            &self.key.read().unwrap_or_else(|e| e.into_inner()),
+        ))
+    }
+}
+
+pub fn acceptor(
+    resolver: Arc<CertResolver>,
+    listener: &ListenerSettings,
+) -> Result<TlsAcceptor, TlsError> {
+    Ok(TlsAcceptor::from(Arc::new(server_config(
+        resolver, listener,
+    )?)))
+}
+
+/// Advertises the listener's HTTP versions via ALPN, preferring h2.
+fn server_config(
+    resolver: Arc<CertResolver>,
+    listener: &ListenerSettings,
+) -> Result<ServerConfig, TlsError> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+
+    if listener.http2 {
+        config.alpn_protocols.push(b"h2".to_vec());
+    }
+    if listener.http1 {
+        config.alpn_protocols.push(b"http/1.1".to_vec());
+    }
+
+    Ok(config)
+}
+
+fn load_certified_key(settings: &TlsSettings) -> Result<CertifiedKey, TlsError> {
+    let cert_path = Path::new(&settings.cert);
+    let key_path = Path::new(&settings.key);
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| TlsError::Pem(cert_path.to_path_buf(), e.to_string()))?;
+
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(cert_path.to_path_buf()));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|e| TlsError::Pem(key_path.to_path_buf(), e.to_string()))?;
+    let signing_key = ring::sign::any_supported_type(&key)
+        .map_err(|e| TlsError::Key(key_path.to_path_buf(), e.to_string()))?;
+
+    let certified = CertifiedKey::new(certs, signing_key);
+
+    // A mismatched pair would fail every handshake.
+    certified.keys_match().map_err(|e| {
+        TlsError::Mismatch(
+            cert_path.to_path_buf(),
+            key_path.to_path_buf(),
+            e.to_string(),
+        )
+    })?;
+
+    Ok(certified)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_cert(dir: &Path, name: &str) -> TlsSettings {
+        let cert = rcgen::generate_simple_self_signed(vec![name.to_string()]).unwrap();
+        let cert_path = dir.join(format!("{}.crt", name));
+        let key_path = dir.join(format!("{}.key", name));
+
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.signing_key.serialize_pem()).unwrap();
+
+        TlsSettings {
+            cert: cert_path.to_string_lossy().into_owned(),
+            key: key_path.to_string_lossy().into_owned(),
+        }
+    }
+
+    fn current(resolver: &CertResolver) -> Vec<u8> {
+        resolver.key.read().unwrap().cert[0].to_vec()
+    }
+
+    #[test]
+    fn test_load_and_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = write_cert(dir.path(), "first.local");
+        let resolver = CertResolver::load(&settings).unwrap();
+        let first = current(&resolver);
+
+        let renewed = write_cert(dir.path(), "second.local");
+        std::fs::copy(&renewed.cert, &settings.cert).unwrap();
+        std::fs::copy(&renewed.key, &settings.key).unwrap();
+        resolver.reload().unwrap();
+
+        assert_ne!(first, current(&resolver));
+    }
+
+    #[test]
+    fn test_reload_keeps_old_cert_on_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = write_cert(dir.path(), "only.local");
+        let resolver = CertResolver::load(&settings).unwrap();
+        let before = current(&resolver);
+
+        std::fs::write(&settings.cert, "not a pem").unwrap();
+
+        assert!(matches!(
+            resolver.reload(),
+            Err(TlsError::NoCertificate(_)) | Err(TlsError::Pem(..))
+        ));
+        assert_eq!(before, current(&resolver));
+
+        let other = write_cert(dir.path(), "other.local");
+        std::fs::copy(&other.cert, &settings.cert).unwrap();
+
+        assert!(matches!(resolver.reload(), Err(TlsError::Mismatch(..))));
+        assert_eq!(before, current(&resolver));
+    }
+
+    #[test]
+    fn test_alpn_follows_listener_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let settings = write_cert(dir.path(), "alpn.local");
+        let resolver = Arc::new(CertResolver::load(&settings).unwrap());
+        let both = server_config(Arc::clone(&resolver), &ListenerSettings::default()).unwrap();
+        let h2_only = ListenerSettings {
+            http1: false,
+            ..Default::default()
+        };
+        let h2_only = server_config(resolver, &h2_only).unwrap();
+
+        assert_eq!(
+            both.alpn_protocols,
+            vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+        );
+        assert_eq!(h2_only.alpn_protocols, vec![b"h2".to_vec()]);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::config::zilliqa::{CHARSET, HRP};
 use sha2::{Digest, Sha256};
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 
 pub const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
 
@@ -12,9 +12,9 @@ pub fn polymod(values: &[u8]) -> u32 {
 
         chk = ((chk & 0x1ffffff) << 5) ^ (*p as u32);
 
-        for i in 0..5 {
+        for (i, generator) in GENERATOR.iter().enumerate() {
             if ((top >> i) & 1) == 1 {
-                chk ^= GENERATOR[i];
+                chk ^= generator;
             }
         }
     }
@@ -69,7 +69,7 @@ pub fn create_checksum(hrp: &str, data: &Vec<u8>) -> Vec<u8> {
     ret
 }
 
-pub fn verify_checksum(hrp: &str, data: &Vec<u8>) -> bool {
+pub fn verify_checksum(hrp: &str, data: &[u8]) -> bool {
     let values = [&hrp_expand(hrp)[..], data].concat();
 
     polymod(&values) == 1
@@ -81,13 +81,13 @@ pub fn decode(bech_string: &str) -> Option<(String, Vec<u8>)> {
 
     for c in bech_string.chars() {
         let code = c as u32;
-        if code < 33 || code > 126 {
+        if !(33..=126).contains(&code) {
             return None;
         }
-        if code >= 97 && code <= 122 {
+        if (97..=122).contains(&code) {
             has_lower = true;
         }
-        if code >= 65 && code <= 90 {
+        if (65..=90).contains(&code) {
             has_upper = true;
         }
     }
@@ -123,8 +123,8 @@ pub fn encode(hrp: &str, data: &Vec<u8>) -> String {
     let combined = [&data[..], &checksum[..]].concat();
     let mut ret = String::from(hrp) + "1"; // hrp is zil so it is zil1.
 
-    for p in 0..combined.len() {
-        let idx = combined[p] as usize;
+    for p in &combined {
+        let idx = *p as usize;
         let value = CHARSET.chars().nth(idx);
 
         match value {
@@ -140,7 +140,7 @@ pub fn get_address_from_public_key(public_key: &str) -> Result<String, Error> {
     let normalized = match hex::decode(public_key.to_lowercase().replace("0x", "")) {
         Ok(h) => h,
         Err(_) => {
-            let pub_key_err = Error::new(ErrorKind::Other, "Invalid pub_key");
+            let pub_key_err = Error::other("Invalid pub_key");
 
             return Err(pub_key_err);
         }
@@ -191,23 +191,29 @@ pub fn convert_bits(data: &Vec<u8>, from_width: u32, to_width: u32, pad: bool) -
 }
 
 pub fn from_bech32_address(address: &str) -> Option<Vec<u8>> {
-    let (hrp, data) = match decode(address) {
-        Some(addr) => addr,
-        None => return None,
-    };
+    let (hrp, data) = decode(address)?;
 
     if hrp != HRP {
         return None;
     }
 
-    let buf = match convert_bits(&data, 5, 8, false) {
-        Some(buf) => buf,
-        None => return None,
-    };
+    let buf = convert_bits(&data, 5, 8, false)?;
 
     Some(buf)
 }
 
+pub fn to_bech32_address(base16: &str) -> Option<String> {
+    let bytes = hex::decode(base16.trim_start_matches("0x")).ok()?;
+
+    if bytes.len() != 20 {
+        return None;
+    }
+
+    let data = convert_bits(&bytes, 8, 5, true)?;
+
+    Some(encode(HRP, &data))
+}
+
 #[test]
 fn test_polymod() {
     let bytes: [u8; 16] = [
@@ -298,3 +304,11 @@ fn test_from_bech32_address() {
 
     assert_eq!(base16, "7793a8e8c09d189d4d421ce5bc5b3674656c5ac1");
 }
+
+#[test]
+fn test_to_bech32_address() {
+    let bech32 = to_bech32_address("0x7793a8e8c09d189d4d421ce5bc5b3674656c5ac1").unwrap();
+
+    assert_eq!(bech32, "zil1w7f636xqn5vf6n2zrnjmckekw3jkckkpyrd6z8");
+    assert_eq!(to_bech32_address("7793a8"), None);
+}
@@ -1,11 +1,13 @@
 use crate::config::zilliqa::PROVIDERS;
+use crate::metrics;
 use log::{error, warn};
 use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
 use reqwest::{Client, Error as ReqwestError};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{from_str, Map, Value};
-use std::io::{Error, ErrorKind};
+use std::io::Error;
+use std::time::Instant;
 use thiserror::Error;
 
 const MAX_BATCH_SIZE: usize = 2;
@@ -61,7 +63,7 @@ pub enum ZilliqaError {
 
 impl From<ZilliqaError> for Error {
     fn from(err: ZilliqaError) -> Self {
-        Error::new(ErrorKind::Other, err.to_string())
+        Error::other(err.to_string())
     }
 }
 
@@ -71,6 +73,12 @@ pub struct Zilliqa {
     client: Client,
 }
 
+impl Default for Zilliqa {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Zilliqa {
     pub fn new() -> Self {
         let providers = PROVIDERS.iter().map(ToString::to_string).collect();
@@ -108,11 +116,18 @@ impl Zilliqa {
         for chunk in bodies.chunks(MAX_BATCH_SIZE) {
             let mut provider_success = false;
 
-            for provider in &self.providers {
+            for (i, provider) in self.providers.iter().enumerate() {
+                let started = Instant::now();
                 let response_result = self
                     .try_fetch_from_provider::<Vec<JsonBodyRes<T>>>(provider, chunk, &headers)
                     .await;
 
+                metrics::observe_upstream(
+                    &format!("zilliqa.providers[{}]", i),
+                    response_result.is_ok(),
+                    started.elapsed(),
+                );
+
                 match response_result {
                     Ok(responses) => {
                         for (i, response) in responses.iter().enumerate() {